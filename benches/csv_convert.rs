@@ -0,0 +1,42 @@
+use std::io;
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rcli::{process_csv, CsvOps};
+
+const ROWS: usize = 200_000;
+
+/// A roster-like CSV with quoted fields and numbers, so `--infer` has some work to do.
+fn generate(rows: usize) -> Vec<u8> {
+    let mut csv = String::from("Name,Position,DOB,Nationality,Kit Number,Rating\n");
+    for i in 0..rows {
+        csv.push_str(&format!(
+            "\"Player {}, Jr.\",Forward,{}-0{}-1{},Italy,{},{}.{}\n",
+            i,
+            1980 + i % 20,
+            1 + i % 9,
+            i % 10,
+            i % 99,
+            i % 10,
+            i % 7
+        ));
+    }
+    csv.into_bytes()
+}
+
+fn bench_process_csv(c: &mut Criterion) {
+    let input = generate(ROWS);
+    let mut group = c.benchmark_group("process_csv");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(10);
+    for threads in ["1", "2", "4", "0"] {
+        let opts = CsvOps::parse_from(["csv", "-i", "-", "--infer", "--threads", threads]);
+        group.bench_with_input(BenchmarkId::new("threads", threads), &input, |b, input| {
+            b.iter(|| process_csv(&mut input.as_slice(), &mut io::sink(), &opts).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_process_csv);
+criterion_main!(benches);
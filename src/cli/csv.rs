@@ -1,15 +1,111 @@
 use super::verify_file;
-use clap::Parser;
-use std::{fmt, str::FromStr};
+use clap::{ArgAction, Parser};
+use encoding_rs::Encoding;
+use std::{fmt, path::Path, str::FromStr};
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Toml,
+    Ndjson,
+    Msgpack,
+    Csv,
+    Tsv,
+    Parquet,
+    Arrow,
 }
+
+/// Format of the records read by `csv from`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Ndjson,
+}
+
+/// Type a CSV cell is converted to in the structured output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+}
+/// Which rows without a match are kept by `csv join`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinHow {
+    Inner,
+    Left,
+    Outer,
+}
+
+/// Encoding of the input CSV, `auto` guesses it from the content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEncoding {
+    Auto,
+    Label(&'static Encoding),
+}
+
+/// `rcli csv`, runs `convert` when no subcommand is given.
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct CsvCommand {
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[command(flatten)]
+    pub convert: CsvOps,
+}
+
+impl CsvCommand {
+    /// The subcommand to run, `convert` with the top level options by default.
+    pub fn into_subcommand(self) -> CsvSubCommand {
+        self.cmd.unwrap_or(CsvSubCommand::Convert(self.convert))
+    }
+}
+
+#[derive(Debug, Parser)]
+pub enum CsvSubCommand {
+    #[command(name = "convert", about = "Convert CSV to other formats")]
+    Convert(CsvOps),
+    #[command(
+        name = "from",
+        about = "Convert JSON, YAML or NDJSON records back into CSV"
+    )]
+    From(CsvFromOps),
+    #[command(
+        name = "mask",
+        about = "Pseudonymize, redact or fake columns of a CSV file"
+    )]
+    Mask(CsvMaskOps),
+    #[command(name = "show", about = "Show CSV as a table in the terminal")]
+    Show(CsvShowOps),
+    #[command(name = "agg", about = "Group CSV rows and count, sum or average them")]
+    Agg(CsvAggOps),
+    #[command(name = "sort", about = "Sort and deduplicate CSV rows")]
+    Sort(CsvSortOps),
+    #[command(name = "join", about = "Join two CSV files on key columns")]
+    Join(CsvJoinOps),
+    #[command(
+        name = "diff",
+        about = "Show rows added, removed or changed between two CSV files"
+    )]
+    Diff(CsvDiffOps),
+    #[command(
+        name = "validate",
+        about = "Check CSV rows against a YAML schema and report every violation as JSON"
+    )]
+    Validate(CsvValidateOps),
+    #[command(name = "query", about = "Run SQL over CSV files loaded as tables")]
+    Query(CsvQueryOps),
+}
+
 #[derive(Debug, Parser)]
 pub struct CsvOps {
-    #[arg(short, long, default_value = "input.csv",value_parser = verify_file)]
+    // 不用 verify_file: `rcli csv show ...` 也会解析这里的默认值, 文件在打开时检查
+    #[arg(short, long, default_value = "input.csv")]
     pub input: String,
 
     #[arg(short, long)] // "output.json".into()
@@ -18,25 +114,433 @@ pub struct CsvOps {
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(long, default_value_t = true)]
+    /// Delimiter of csv / tsv output
+    #[arg(long, value_parser = parse_csv_char)]
+    pub output_delimiter: Option<u8>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+
+    /// Detect integers, floats, booleans, ISO-8601 dates and empty cells (null) instead of emitting strings;
+    /// always on for parquet and arrow output
+    #[arg(long)]
+    pub infer: bool,
+
+    /// Pin a column to a type, e.g. `--schema "Kit Number=int"`; cells that fail to parse are reported
+    #[arg(long, value_parser = parse_schema_field, value_delimiter = ',')]
+    pub schema: Vec<(String, CellType)>,
+
+    /// Only output these columns, in this order
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Rename a column in the output, e.g. `--rename "Kit Number=kit"`
+    #[arg(long, value_parser = parse_rename, value_delimiter = ',')]
+    pub rename: Vec<(String, String)>,
+
+    /// Only keep records matching the expression, e.g. `Position == "Goalkeeper" && Kit Number > 10`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+
+    /// Build nested objects and arrays from `address.city` and `tags[0]` column names, the reverse of `csv from`
+    #[arg(long)]
+    pub unflatten: bool,
+
+    /// Separator of nested keys for --unflatten
+    #[arg(long, value_parser = parse_separator, default_value = ".")]
+    pub separator: String,
+
+    /// Start a new output file every N rows, `{n}` in the output name is the file number
+    #[arg(long, conflicts_with = "split_by")]
+    pub split_rows: Option<usize>,
+
+    /// Write one output file per value of this column, `{key}` in the output name is the value
+    #[arg(long)]
+    pub split_by: Option<String>,
+
+    /// Parse and convert on this many threads, 0 uses every core
+    #[arg(long, default_value_t = 1, conflicts_with_all = ["split_rows", "split_by"])]
+    pub threads: usize,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvFromOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Format of the input (json, yaml or ndjson), guessed from the file extension when omitted
+    #[arg(long, value_parser = parse_input_format)]
+    pub format: Option<InputFormat>,
+
+    /// Joins the keys of nested objects into column names, e.g. `address.city`
+    #[arg(long, value_parser = parse_separator, default_value = ".")]
+    pub separator: String,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvMaskOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Replace with a keyed BLAKE3 pseudonym, the same value always maps to the same pseudonym for a key
+    #[arg(long, value_delimiter = ',')]
+    pub hash: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub redact: Vec<String>,
+
+    /// Replace with a fake value of the same shape (letters stay letters, digits stay digits)
+    #[arg(long, value_delimiter = ',')]
+    pub fake: Vec<String>,
+
+    /// Shift dates by --shift-days, or by an offset derived from the key
+    #[arg(long, value_delimiter = ',')]
+    pub shift_date: Vec<String>,
+
+    #[arg(long, allow_hyphen_values = true)]
+    pub shift_days: Option<i64>,
+
+    #[arg(long, default_value = "***")]
+    pub redact_with: String,
+
+    /// BLAKE3 key file (e.g. from `rcli text generate`) for --hash, --fake and --shift-date
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Rows per page
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub limit: usize,
+
+    #[arg(short, long, default_value_t = 1)]
+    pub page: usize,
+
+    /// Cells wider than this are truncated
+    #[arg(long, default_value_t = 40)]
+    pub max_width: usize,
+
+    /// Show count, nulls, distinct values and numeric min / max per column instead of rows
+    #[arg(long)]
+    pub stats: bool,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvAggOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    /// Columns to group by, without it the whole input is one group
+    #[arg(long, value_delimiter = ',')]
+    pub group_by: Vec<String>,
+
+    /// Number of rows in each group
+    #[arg(long)]
+    pub count: bool,
+
+    #[arg(long, value_delimiter = ',')]
+    pub sum: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub avg: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub min: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub max: Vec<String>,
+
+    /// Only aggregate rows matching the expression
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSortOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Columns to sort by, numbers and dates are compared by value
+    #[arg(long, value_delimiter = ',')]
+    pub by: Vec<String>,
+
+    #[arg(long)]
+    pub desc: bool,
+
+    /// Keep only the first row for each value of these columns
+    #[arg(long, value_delimiter = ',')]
+    pub dedup_by: Vec<String>,
+
+    /// Rows sorted in memory before spilling a run to disk
+    #[arg(long, default_value_t = 100_000)]
+    pub buffer_rows: usize,
+
+    /// Where sorted runs are written, defaults to the system temp directory
+    #[arg(long)]
+    pub temp_dir: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOps {
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long, value_parser = parse_format, default_value = "csv")]
+    pub format: OutputFormat,
+
+    /// Key columns, present in both files
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+
+    #[arg(long, value_parser = parse_join_how, default_value = "inner")]
+    pub how: JoinHow,
+
+    /// Prefix of left columns whose name is also used on the right
+    #[arg(long, default_value = "left.")]
+    pub left_prefix: String,
+
+    #[arg(long, default_value = "right.")]
+    pub right_prefix: String,
+
+    /// Right rows held in memory for a hash join, larger inputs are sorted on disk and merged
+    #[arg(long, default_value_t = 100_000)]
+    pub buffer_rows: usize,
+
+    #[arg(long)]
+    pub temp_dir: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOps {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Columns identifying a row in both files
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+
+    /// Write one patch record per change in this format instead of a table
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOps {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// YAML file with the rules per column: required, type, pattern, enum, unique, min and max
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+
+    /// Where to write the JSON report, stdout by default
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOps {
+    /// SQLite SQL, e.g. `SELECT Nationality, COUNT(*) FROM juventus GROUP BY Nationality`
+    pub sql: String,
+
+    /// Files to load, each as a table named after the file (`juventus.csv` is `juventus`) or `name=path`
+    #[arg(value_parser = parse_table, required = true)]
+    pub tables: Vec<(String, String)>,
+
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Write the result rows in this format instead of a table
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[command(flatten)]
+    pub read: CsvReadOps,
+}
+
+/// How the input CSV is laid out, shared by every command reading CSV.
+#[derive(Debug, Clone, Parser)]
+pub struct CsvReadOps {
+    /// Whether the first row holds the column names; with `--header false` columns are named column_1..N
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub header: bool,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    #[arg(short, long, value_parser = parse_csv_char, default_value = ",")]
+    pub delimiter: u8,
+
+    #[arg(long, value_parser = parse_csv_char, default_value = "\"")]
+    pub quote: u8,
+
+    #[arg(long, value_parser = parse_csv_char)]
+    pub escape: Option<u8>,
+
+    /// Lines starting with this character are skipped
+    #[arg(long, value_parser = parse_csv_char)]
+    pub comment: Option<u8>,
+
+    /// Encoding of the input, e.g. gbk, shift_jis, utf-16le or auto; a BOM always wins
+    #[arg(long, value_parser = parse_input_encoding, default_value = "utf-8")]
+    pub encoding: InputEncoding,
+
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8")]
+    pub output_encoding: &'static Encoding,
+
+    /// Sheet to read from an Excel (.xlsx, .xls) or OpenDocument workbook, by name; the first by default
+    #[arg(long)]
+    pub sheet: Option<String>,
+}
+
+// csv 只支持单字节的分隔符, "\t" 或 "tab" 表示制表符
+fn parse_csv_char(s: &str) -> Result<u8, &'static str> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => match s.as_bytes() {
+            [c] if c.is_ascii() => Ok(*c),
+            _ => Err("Must be a single ASCII character"),
+        },
+    }
+}
+
+fn parse_separator(s: &str) -> Result<String, &'static str> {
+    if s.is_empty() {
+        return Err("The separator must not be empty");
+    }
+    Ok(s.to_string())
+}
+
+fn parse_input_encoding(s: &str) -> Result<InputEncoding, &'static str> {
+    match s {
+        "auto" => Ok(InputEncoding::Auto),
+        _ => parse_encoding(s).map(InputEncoding::Label),
+    }
+}
+
+fn parse_encoding(s: &str) -> Result<&'static Encoding, &'static str> {
+    Encoding::for_label(s.as_bytes()).ok_or("Unknown encoding")
+}
+
+fn parse_schema_field(s: &str) -> Result<(String, CellType), anyhow::Error> {
+    let (name, ty) = s
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Schema field must look like `column=type`"))?;
+    Ok((name.trim().to_string(), ty.trim().parse()?))
 }
 
-fn parse_format(format_str: &str) -> Result<OutputFormat, anyhow::Error> {
+fn parse_rename(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err("Rename must look like `old=new`"),
+    }
+}
+
+// 表名取文件名去掉所有扩展名, 不能做标识符的字符换成 _
+fn parse_table(s: &str) -> Result<(String, String), &'static str> {
+    let (name, path) = match s.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !Path::new(s).exists() => {
+            (name.to_string(), path)
+        }
+        _ => {
+            let file = Path::new(s).file_name().and_then(|f| f.to_str());
+            let stem = match file {
+                Some(file) if s != "-" => file.split('.').next().unwrap_or(file),
+                _ => "stdin",
+            };
+            let name = stem
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                .collect();
+            (name, s)
+        }
+    };
+    Ok((name, verify_file(path)?))
+}
+
+pub(super) fn parse_format(format_str: &str) -> Result<OutputFormat, anyhow::Error> {
     // 调用了parse()方法，用于将字符串解析为指定的类型。在这里，它试图将format_str解析为OutputFormat类型
     // 告诉编译器要将字符串解析为OutputFormat类型的值
     format_str.parse::<OutputFormat>()
 }
 
+fn parse_input_format(format: &str) -> Result<InputFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_join_how(how: &str) -> Result<JoinHow, anyhow::Error> {
+    how.parse()
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
-            // OutputFormat::Toml => "toml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Msgpack => "msgpack",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
         }
     }
 }
@@ -48,14 +552,207 @@ impl FromStr for OutputFormat {
         match s {
             "json" => Ok(OutputFormat::Json),
             "yaml" => Ok(OutputFormat::Yaml),
-            // "toml" => Ok(OutputFormat::Toml),
+            "toml" => Ok(OutputFormat::Toml),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "msgpack" => Ok(OutputFormat::Msgpack),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" => Ok(OutputFormat::Arrow),
             _ => Err(anyhow::anyhow!("UnSupported format.")),
         }
     }
 }
+impl OutputFormat {
+    /// Written as bytes rather than text, so `--output-encoding` does not apply.
+    pub fn is_binary(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Msgpack | OutputFormat::Parquet | OutputFormat::Arrow
+        )
+    }
+}
+
 // into使用
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+impl FromStr for CellType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(CellType::String),
+            "int" => Ok(CellType::Int),
+            "float" => Ok(CellType::Float),
+            "bool" => Ok(CellType::Bool),
+            "date" => Ok(CellType::Date),
+            _ => Err(anyhow::anyhow!("UnSupported type.")),
+        }
+    }
+}
+
+impl From<CellType> for &'static str {
+    fn from(ty: CellType) -> Self {
+        match ty {
+            CellType::String => "string",
+            CellType::Int => "int",
+            CellType::Float => "float",
+            CellType::Bool => "bool",
+            CellType::Date => "date",
+        }
+    }
+}
+
+impl fmt::Display for CellType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(InputFormat::Json),
+            "yaml" => Ok(InputFormat::Yaml),
+            "ndjson" => Ok(InputFormat::Ndjson),
+            _ => Err(anyhow::anyhow!(
+                "UnSupported input format, use json, yaml or ndjson."
+            )),
+        }
+    }
+}
+
+impl From<InputFormat> for &'static str {
+    fn from(format: InputFormat) -> Self {
+        match format {
+            InputFormat::Json => "json",
+            InputFormat::Yaml => "yaml",
+            InputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for JoinHow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinHow::Inner),
+            "left" => Ok(JoinHow::Left),
+            "outer" => Ok(JoinHow::Outer),
+            _ => Err(anyhow::anyhow!(
+                "UnSupported join, use inner, left or outer."
+            )),
+        }
+    }
+}
+
+impl From<JoinHow> for &'static str {
+    fn from(how: JoinHow) -> Self {
+        match how {
+            JoinHow::Inner => "inner",
+            JoinHow::Left => "left",
+            JoinHow::Outer => "outer",
+        }
+    }
+}
+
+impl fmt::Display for JoinHow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_char() {
+        assert_eq!(parse_csv_char(";"), Ok(b';'));
+        assert_eq!(parse_csv_char("\\t"), Ok(b'\t'));
+        assert_eq!(parse_csv_char("tab"), Ok(b'\t'));
+        assert!(parse_csv_char(";;").is_err());
+        assert!(parse_csv_char("é").is_err());
+    }
+
+    #[test]
+    fn test_parse_input_format() {
+        assert_eq!(parse_input_format("ndjson").unwrap(), InputFormat::Ndjson);
+        assert!(parse_input_format("toml").is_err());
+        assert!(parse_input_format("parquet").is_err());
+    }
+
+    #[test]
+    fn test_validate_input() {
+        let args = [
+            "csv",
+            "validate",
+            "-i",
+            "assets/juventus.csv",
+            "-s",
+            "Cargo.toml",
+        ];
+        match CsvSubCommand::parse_from(args) {
+            CsvSubCommand::Validate(opts) => assert_eq!(opts.input, "assets/juventus.csv"),
+            cmd => panic!("unexpected subcommand {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(parse_input_encoding("auto"), Ok(InputEncoding::Auto));
+        assert_eq!(
+            parse_input_encoding("GBK"),
+            Ok(InputEncoding::Label(encoding_rs::GBK))
+        );
+        assert_eq!(parse_encoding("utf-16"), Ok(encoding_rs::UTF_16LE));
+        assert!(parse_encoding("utf-42").is_err());
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let table = |name: &str, path: &str| Ok((name.to_string(), path.to_string()));
+        assert_eq!(
+            parse_table("assets/juventus.csv"),
+            table("juventus", "assets/juventus.csv")
+        );
+        assert_eq!(
+            parse_table("players=assets/juventus.csv"),
+            table("players", "assets/juventus.csv")
+        );
+        assert_eq!(parse_table("-"), table("stdin", "-"));
+        assert!(parse_table("players=missing.csv").is_err());
+    }
+
+    #[test]
+    fn test_parse_schema_field() {
+        let (name, ty) = parse_schema_field("Kit Number=int").unwrap();
+        assert_eq!(name, "Kit Number");
+        assert_eq!(ty, CellType::Int);
+        assert!(parse_schema_field("Kit Number").is_err());
+        assert!(parse_schema_field("DOB=timestamp").is_err());
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(
+            parse_rename("Kit Number=kit"),
+            Ok(("Kit Number".into(), "kit".into()))
+        );
+        assert!(parse_rename("Kit Number").is_err());
+        assert!(parse_rename("=kit").is_err());
+    }
+}
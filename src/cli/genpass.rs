@@ -1,19 +1,235 @@
-use clap::Parser;
+use super::csv::parse_format;
+use super::{verify_file, OutputFormat};
+use clap::{ArgAction, Parser};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Parser)]
 pub struct GenPassOps {
     #[arg(short, long, default_value_t = 16)]
     pub length: u8,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub upper: bool,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub lower: bool,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub number: bool,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub symbol: bool,
+
+    /// Number of passwords to generate
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+
+    /// Write the passwords with their zxcvbn score, crack time and entropy as json, csv, yaml, ...
+    #[arg(long, value_parser = parse_format)]
+    pub output: Option<OutputFormat>,
+
+    /// Draw from a preset instead of all four classes: alnum, hex, base58 or pin
+    #[arg(long, value_parser = parse_charset, conflicts_with = "words")]
+    pub charset: Option<Charset>,
+
+    /// Draw from exactly these characters, e.g. "abcdef123456"; each class they
+    /// contain still appears at least once
+    #[arg(
+        long,
+        value_parser = parse_chars,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["charset", "symbols", "words"]
+    )]
+    pub chars: Option<String>,
+
+    /// Symbols to use instead of !@#$%^&*_
+    #[arg(long, value_parser = parse_symbols, allow_hyphen_values = true)]
+    pub symbols: Option<String>,
+
+    /// Also use I, O, l and 0, which are easy to mistake for each other
+    #[arg(long, conflicts_with_all = ["words", "chars"])]
+    pub include_ambiguous: bool,
+
+    /// Generate a passphrase of N words instead of a password
+    #[arg(long, conflicts_with = "length")]
+    pub words: Option<usize>,
+
+    /// Draw the words from this file instead of the EFF large wordlist,
+    /// one word per line, diceware style `11111 word` lines also work
+    #[arg(long, value_parser = verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+
+    /// Put between the words of a passphrase
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+
+    /// Capitalize the words of a passphrase: none, first, upper or random
+    #[arg(long, value_parser = parse_capitalize, default_value = "none", requires = "words")]
+    pub capitalize: Capitalize,
+
+    /// Append a random digit to the passphrase
+    #[arg(long, requires = "words")]
+    pub append_digit: bool,
+
+    /// Append a random symbol to the passphrase
+    #[arg(long, requires = "words")]
+    pub append_symbol: bool,
+
+    #[command(flatten)]
+    pub policy: PasswordPolicy,
+}
+
+/// What a generated password must satisfy, passwords are regenerated until it does.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct PasswordPolicy {
+    /// Refuse a --length shorter than this
+    #[arg(long, conflicts_with = "words")]
+    pub min_length: Option<u8>,
+
+    /// Minimum number of uppercase letters, 1 when --upper is on
+    #[arg(long, conflicts_with = "words")]
+    pub min_upper: Option<u8>,
+
+    /// Minimum number of lowercase letters, 1 when --lower is on
+    #[arg(long, conflicts_with = "words")]
+    pub min_lower: Option<u8>,
+
+    /// Minimum number of digits, 1 when --number is on
+    #[arg(long, conflicts_with = "words")]
+    pub min_number: Option<u8>,
+
+    /// Minimum number of symbols, 1 when --symbol is on
+    #[arg(long, conflicts_with = "words")]
+    pub min_symbol: Option<u8>,
+
+    /// Characters that must not appear in the password
+    #[arg(long, default_value = "", conflicts_with = "words")]
+    pub exclude_chars: String,
+
+    /// Most times the same character may appear in a row
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+    pub max_repeat: Option<u8>,
+
+    /// Minimum zxcvbn score, from 0 (weakest) to 4
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+
+    /// Minimum entropy in bits
+    #[arg(long)]
+    pub min_entropy: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// Letters and digits
+    Alnum,
+    /// Lowercase hex digits
+    Hex,
+    /// Bitcoin's base58, letters and digits without 0, O, I and l
+    Base58,
+    /// Digits only
+    Pin,
+}
+
+fn parse_charset(charset: &str) -> Result<Charset, anyhow::Error> {
+    charset.parse()
+}
+
+impl FromStr for Charset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alnum" => Ok(Charset::Alnum),
+            "hex" => Ok(Charset::Hex),
+            "base58" => Ok(Charset::Base58),
+            "pin" => Ok(Charset::Pin),
+            _ => Err(anyhow::anyhow!("Invalid charset")),
+        }
+    }
+}
+
+impl From<Charset> for &'static str {
+    fn from(charset: Charset) -> Self {
+        match charset {
+            Charset::Alnum => "alnum",
+            Charset::Hex => "hex",
+            Charset::Base58 => "base58",
+            Charset::Pin => "pin",
+        }
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+fn parse_chars(chars: &str) -> Result<String, anyhow::Error> {
+    if chars.is_empty() || !chars.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(anyhow::anyhow!(
+            "Characters must be printable ASCII without spaces, e.g. \"abc123\""
+        ));
+    }
+    // 重复的字符只保留一个, 否则它们被选中的概率更高
+    let mut seen = HashSet::new();
+    Ok(chars.chars().filter(|c| seen.insert(*c)).collect())
+}
+
+fn parse_symbols(symbols: &str) -> Result<String, anyhow::Error> {
+    if symbols.is_empty() || !symbols.chars().all(|c| c.is_ascii_punctuation()) {
+        return Err(anyhow::anyhow!(
+            "Symbols must be ASCII punctuation, e.g. \"-_.!\""
+        ));
+    }
+    Ok(symbols.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capitalize {
+    None,
+    /// The first letter of each word
+    First,
+    /// The whole word
+    Upper,
+    /// The first letter of each word, or not, at random
+    Random,
+}
+
+fn parse_capitalize(capitalize: &str) -> Result<Capitalize, anyhow::Error> {
+    capitalize.parse()
+}
+
+impl FromStr for Capitalize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Capitalize::None),
+            "first" => Ok(Capitalize::First),
+            "upper" => Ok(Capitalize::Upper),
+            "random" => Ok(Capitalize::Random),
+            _ => Err(anyhow::anyhow!("Invalid capitalization")),
+        }
+    }
+}
+
+impl From<Capitalize> for &'static str {
+    fn from(capitalize: Capitalize) -> Self {
+        match capitalize {
+            Capitalize::None => "none",
+            Capitalize::First => "first",
+            Capitalize::Upper => "upper",
+            Capitalize::Random => "random",
+        }
+    }
+}
+
+impl fmt::Display for Capitalize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
 }
@@ -2,18 +2,23 @@ mod base64;
 mod csv;
 mod genpass;
 mod http;
+mod password;
 mod text;
 use std::path::Path;
 use std::path::PathBuf;
 
 use clap::Parser;
 
-use self::{csv::CsvOps, genpass::GenPassOps};
-
 pub use self::{
     base64::{Base64Format, Base64Subcommand},
-    csv::OutputFormat,
+    csv::{
+        CellType, CsvAggOps, CsvCommand, CsvDiffOps, CsvFromOps, CsvJoinOps, CsvMaskOps, CsvOps,
+        CsvQueryOps, CsvReadOps, CsvShowOps, CsvSortOps, CsvSubCommand, CsvValidateOps,
+        InputEncoding, InputFormat, JoinHow, OutputFormat,
+    },
+    genpass::{Capitalize, Charset, GenPassOps, PasswordPolicy},
     http::HttpSubCommand,
+    password::{PasswordAuditOps, PasswordSubCommand},
     text::{TextSignFormat, TextSignOps, TextSubCommand, TextVerifyOps},
 };
 
@@ -26,12 +31,17 @@ pub struct Opts {
 
 #[derive(Debug, Parser)]
 pub enum SubCommand {
-    #[command(name = "csv", about = "Show CSV , or Convert CSV to other formats")]
-    Csv(CsvOps),
+    #[command(
+        about = "Show CSV , or Convert CSV to other formats, `rcli csv -i ...` runs convert"
+    )]
+    Csv(Box<CsvCommand>),
 
     #[command(name = "genpass", about = "generate a random password")]
     GenPass(GenPassOps),
 
+    #[command(subcommand, about = "audit the strength of existing passwords")]
+    Password(PasswordSubCommand),
+
     #[command(subcommand)]
     Base64(Base64Subcommand),
 
@@ -72,4 +82,20 @@ mod tests {
         assert_eq!(verify_file("Cargo.toml"), Ok("Cargo.toml".into()));
         assert_eq!(verify_file("not-exist"), Err("File does not exist"));
     }
+
+    #[test]
+    fn test_default_csv_subcommand() {
+        let parse = |args: &[&str]| match Opts::parse_from(args).cmd {
+            SubCommand::Csv(cmd) => cmd.into_subcommand(),
+            cmd => panic!("expected csv, got {:?}", cmd),
+        };
+        match parse(&["rcli", "csv", "-i", "assets/juventus.csv", "-o", "out.json"]) {
+            CsvSubCommand::Convert(opts) => assert_eq!(opts.output.as_deref(), Some("out.json")),
+            cmd => panic!("expected convert, got {:?}", cmd),
+        }
+        let cmd = parse(&["rcli", "csv", "show", "-i", "assets/juventus.csv"]);
+        assert!(matches!(cmd, CsvSubCommand::Show(_)));
+        let args = ["rcli", "csv", "-i", "assets/juventus.csv", "show"];
+        assert!(Opts::try_parse_from(args).is_err());
+    }
 }
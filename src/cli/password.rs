@@ -0,0 +1,32 @@
+use super::csv::parse_format;
+use super::{verify_file, OutputFormat};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub enum PasswordSubCommand {
+    #[command(name = "audit", about = "report the strength of existing passwords")]
+    Audit(PasswordAuditOps),
+}
+
+#[derive(Debug, Parser)]
+pub struct PasswordAuditOps {
+    /// One password per line, empty lines are skipped
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Words the passwords should not be based on, e.g. user or company names
+    #[arg(short, long = "user-input", value_delimiter = ',')]
+    pub user_inputs: Vec<String>,
+
+    /// Exit with an error if any password scores below this, from 0 to 4
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+
+    /// Write the report as json, csv, yaml, ... instead of text
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    /// Include the passwords in the report, by default they are only referred to by line
+    #[arg(long)]
+    pub reveal: bool,
+}
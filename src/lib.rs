@@ -5,8 +5,15 @@ mod utils;
 
 // 使用 use 导入,
 // 使用 pub 方便外部直接使用
-pub use cli::TextSignFormat;
-pub use cli::{Base64Subcommand, HttpSubCommand, SubCommand, TextSubCommand};
-pub use cli::{Opts, TextSignOps, TextVerifyOps};
+pub use cli::{
+    Base64Subcommand, HttpSubCommand, PasswordAuditOps, PasswordSubCommand, SubCommand,
+    TextSubCommand,
+};
+pub use cli::{Capitalize, Charset, GenPassOps, Opts, PasswordPolicy, TextSignOps, TextVerifyOps};
+pub use cli::{
+    CellType, CsvAggOps, CsvDiffOps, CsvFromOps, CsvJoinOps, CsvMaskOps, CsvOps, CsvQueryOps,
+    CsvReadOps, CsvShowOps, CsvSortOps, CsvSubCommand, CsvValidateOps, InputEncoding, InputFormat,
+    JoinHow, OutputFormat, TextSignFormat,
+};
 pub use process::*;
 pub use utils::*;
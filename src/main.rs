@@ -3,16 +3,30 @@ use std::fs;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use clap::Parser;
 use rcli::{
-    get_content, get_reader, Base64Subcommand, HttpSubCommand, Opts, SubCommand, TextSubCommand,
+    csv_input_reader, encode_writer, get_content, get_reader, get_uncompressed_reader, get_writer,
+    Base64Subcommand, CsvSubCommand, GenPassOptions, HttpSubCommand, Opts, PassphraseOptions,
+    PasswordSubCommand, SubCommand, TextSubCommand,
 };
 
 use rcli::{
-    process_csv, process_decode, process_encode, process_genpass, process_http_serve,
-    process_text_key_generate, process_text_sign, process_text_verify,
+    process_csv, process_csv_agg, process_csv_diff, process_csv_from, process_csv_join,
+    process_csv_mask, process_csv_query, process_csv_show, process_csv_sort, process_csv_split,
+    process_csv_validate, process_decode, process_encode, process_genpass, process_genpass_records,
+    process_http_serve, process_passphrase, process_password_audit, process_text_key_generate,
+    process_text_sign, process_text_verify,
 };
 use zxcvbn::zxcvbn;
 
-// rcli csv -i input.csv -o output.csv --header -d ','
+// rcli csv convert -i input.csv -o output.json --header true -d ','
+// rcli csv -i input.csv -o output.json
+// rcli csv convert -i - --split-by Nationality -o 'players-{key}.json'
+// rcli csv query "SELECT Nationality, COUNT(*) FROM juventus GROUP BY Nationality" juventus.csv
+// rcli genpass -l 20 --symbol false --min-number 3 --min-score 4
+// rcli genpass -l 32 --charset hex
+// rcli genpass -l 12 --chars 'abcdefghjk23456789'
+// rcli genpass -n 100 --output csv > passwords.csv
+// rcli genpass --words 6 --capitalize first --append-digit
+// rcli password audit -i passwords.txt -u jdoe,acme --min-score 3
 // rcli base64 encode -i xxx.toml --format urlsafe
 
 #[tokio::main]
@@ -22,28 +36,162 @@ async fn main() -> anyhow::Result<()> {
     let opts: Opts = Opts::parse();
     // println!("{:?}", opts)
     match opts.cmd {
-        SubCommand::Csv(opts) => {
-            let output = if let Some(output) = opts.output {
-                output.clone()
-            } else {
-                format!("{}.{}", opts.input, opts.format)
-            };
-            process_csv(&opts.input, output, opts.format)?;
-        }
+        SubCommand::Csv(subcmd) => match subcmd.into_subcommand() {
+            CsvSubCommand::Convert(opts)
+                if opts.split_rows.is_some() || opts.split_by.is_some() =>
+            {
+                let template = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    let placeholder = if opts.split_rows.is_some() {
+                        "{n}"
+                    } else {
+                        "{key}"
+                    };
+                    default_output(&opts.input, &format!("{}.{}", placeholder, opts.format))
+                };
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let encoding = opts.read.output_encoding;
+                process_csv_split(&mut reader, &opts, &template, &mut |name| {
+                    Ok(encode_writer(get_writer(name)?, encoding))
+                })?;
+            }
+            CsvSubCommand::Convert(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.input, &opts.format.to_string())
+                };
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv(&mut reader, &mut writer, &opts)?;
+            }
+            CsvSubCommand::From(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.input, "csv")
+                };
+                let mut reader = get_uncompressed_reader(&opts.input)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv_from(&mut reader, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Mask(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.input, "masked.csv")
+                };
+                let key = opts.key.as_deref().map(get_content).transpose()?;
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv_mask(&mut reader, &mut writer, key.as_deref(), &opts)?;
+            }
+            CsvSubCommand::Show(opts) => {
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let ret = process_csv_show(&mut reader, &opts)?;
+                print!("{}", ret);
+            }
+            CsvSubCommand::Agg(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.input, &format!("agg.{}", opts.format))
+                };
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv_agg(&mut reader, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Sort(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.input, "sorted.csv")
+                };
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv_sort(&mut reader, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Join(opts) => {
+                let output = if let Some(output) = &opts.output {
+                    output.clone()
+                } else {
+                    default_output(&opts.left, &format!("joined.{}", opts.format))
+                };
+                let mut left = csv_input_reader(&opts.left, &opts.read)?;
+                let mut right = csv_input_reader(&opts.right, &opts.read)?;
+                let mut writer = encode_writer(get_writer(&output)?, opts.read.output_encoding);
+                process_csv_join(&mut left, &mut right, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Diff(opts) => {
+                let mut old = csv_input_reader(&opts.old, &opts.read)?;
+                let mut new = csv_input_reader(&opts.new, &opts.read)?;
+                let output = opts.output.as_deref().unwrap_or("-");
+                let mut writer = encode_writer(get_writer(output)?, opts.read.output_encoding);
+                process_csv_diff(&mut old, &mut new, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Query(opts) => {
+                let mut tables = opts
+                    .tables
+                    .iter()
+                    .map(|(name, path)| Ok((name.clone(), csv_input_reader(path, &opts.read)?)))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let output = opts.output.as_deref().unwrap_or("-");
+                let mut writer = encode_writer(get_writer(output)?, opts.read.output_encoding);
+                process_csv_query(&mut tables, &mut writer, &opts)?;
+            }
+            CsvSubCommand::Validate(opts) => {
+                let schema = get_content(&opts.schema)?;
+                let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+                let output = opts.output.as_deref().unwrap_or("-");
+                let mut writer = encode_writer(get_writer(output)?, opts.read.output_encoding);
+                let errors = process_csv_validate(&mut reader, &schema, &mut writer, &opts)?;
+                // 报告写完后以非 0 退出, 方便 CI 拦截
+                if errors > 0 {
+                    drop(writer);
+                    anyhow::bail!("{} validation error(s) in {}", errors, opts.input);
+                }
+            }
+        },
         SubCommand::GenPass(opts) => {
-            let password = process_genpass(
-                opts.length,
-                opts.upper,
-                opts.lower,
-                opts.number,
-                opts.symbol,
-            )?;
-            println!("Password: {}", password);
+            let wordlist = opts
+                .wordlist
+                .as_deref()
+                .map(fs::read_to_string)
+                .transpose()?;
+            if let Some(format) = opts.output {
+                let mut writer = get_writer("-")?;
+                process_genpass_records(&opts, wordlist.as_deref(), &mut writer, format)?;
+                return Ok(());
+            }
+            let password_opts = GenPassOptions::from(&opts);
+            let passphrase_opts = PassphraseOptions::from(&opts);
+            for _ in 0..opts.count {
+                let (password, entropy) = if opts.words.is_some() {
+                    process_passphrase(&passphrase_opts, wordlist.as_deref())?
+                } else {
+                    process_genpass(&password_opts)?
+                };
+                println!("Password: {}", password);
 
-            // output password strength in stderr
-            let estimate = zxcvbn(&password, &[])?;
-            eprintln!("Password strength: {}", estimate.score());
+                // output password strength in stderr
+                let estimate = zxcvbn(&password, &[])?;
+                eprintln!("Password strength: {}", estimate.score());
+                eprintln!("Entropy: {:.1} bits", entropy);
+            }
         }
+        SubCommand::Password(subcmd) => match subcmd {
+            PasswordSubCommand::Audit(opts) => {
+                let mut reader = get_reader(&opts.input)?;
+                let mut writer = get_writer("-")?;
+                let weak = process_password_audit(&mut reader, &mut writer, &opts)?;
+                // 和 csv validate 一样, 报告写完后以非 0 退出
+                if weak > 0 {
+                    drop(writer);
+                    anyhow::bail!("{} password(s) score below {}", weak, opts.min_score);
+                }
+            }
+        },
         SubCommand::Base64(subcmd) => match subcmd {
             Base64Subcommand::Encode(opts) => {
                 let mut reader = get_reader(&opts.input)?;
@@ -101,3 +249,9 @@ async fn main() -> anyhow::Result<()> {
 // println!("{:?}", records);
 
 // }
+
+// 从 stdin 读取时没有文件名可用, 默认输出到 output.xxx
+fn default_output(input: &str, ext: &str) -> String {
+    let stem = if input == "-" { "output" } else { input };
+    format!("{}.{}", stem, ext)
+}
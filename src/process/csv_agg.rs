@@ -0,0 +1,336 @@
+use std::{collections::HashMap, io::Read};
+
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+use serde_json::{Map, Number, Value};
+
+use super::csv_convert::{csv_headers, csv_reader};
+use super::csv_encoding::check_output_encoding;
+use super::csv_filter::Filter;
+use super::csv_output::record_writer;
+use crate::cli::CsvAggOps;
+use crate::FinishWrite;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Agg {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Agg {
+    fn name(&self) -> &'static str {
+        match self {
+            Agg::Sum => "sum",
+            Agg::Avg => "avg",
+            Agg::Min => "min",
+            Agg::Max => "max",
+        }
+    }
+}
+
+/// Running totals of one column in one group, empty cells are skipped.
+#[derive(Debug, Clone)]
+struct Acc {
+    n: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+    // 全部是整数时另外精确累加, sum / min / max 输出整数
+    ints: Option<Ints>,
+}
+
+/// Exact totals while every cell is an integer, f64 loses precision past 2^53.
+#[derive(Debug, Clone)]
+struct Ints {
+    sum: i128,
+    min: i64,
+    max: i64,
+}
+
+impl Default for Acc {
+    fn default() -> Self {
+        Self {
+            n: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            ints: Some(Ints {
+                sum: 0,
+                min: i64::MAX,
+                max: i64::MIN,
+            }),
+        }
+    }
+}
+
+impl Acc {
+    fn update(&mut self, field: &str) -> Result<()> {
+        if field.is_empty() {
+            return Ok(());
+        }
+        let n = match field.parse::<f64>() {
+            Ok(n) if n.is_finite() => n,
+            _ => return Err(anyhow!("`{}` is not a number", field)),
+        };
+        match (&mut self.ints, field.parse::<i64>()) {
+            (Some(ints), Ok(i)) => match ints.sum.checked_add(i.into()) {
+                Some(sum) => {
+                    ints.sum = sum;
+                    ints.min = ints.min.min(i);
+                    ints.max = ints.max.max(i);
+                }
+                None => self.ints = None,
+            },
+            // 出现非整数后改用 f64
+            _ => self.ints = None,
+        }
+        self.n += 1;
+        self.sum += n;
+        self.min = self.min.min(n);
+        self.max = self.max.max(n);
+        Ok(())
+    }
+
+    fn value(&self, agg: Agg) -> Value {
+        if self.n == 0 {
+            return match agg {
+                Agg::Sum => Value::from(0),
+                _ => Value::Null,
+            };
+        }
+        let Some(ints) = &self.ints else {
+            return number(match agg {
+                Agg::Sum => self.sum,
+                Agg::Avg => self.sum / self.n as f64,
+                Agg::Min => self.min,
+                Agg::Max => self.max,
+            });
+        };
+        match agg {
+            // 超出 64 位的和输出为浮点数
+            Agg::Sum => {
+                i64::try_from(ints.sum).map_or_else(|_| number(ints.sum as f64), Value::from)
+            }
+            Agg::Avg => number(ints.sum as f64 / self.n as f64),
+            Agg::Min => Value::from(ints.min),
+            Agg::Max => Value::from(ints.max),
+        }
+    }
+}
+
+fn number(n: f64) -> Value {
+    Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+struct Group {
+    key: Vec<String>,
+    count: usize,
+    accs: Vec<Acc>,
+}
+
+/// Group rows by `--group-by` and aggregate them in a single pass.
+///
+/// Only one row per group is kept in memory, so the input can be of any size as
+/// long as the groups fit. Groups are written in the order they are first seen.
+pub fn process_csv_agg(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvAggOps,
+) -> Result<()> {
+    check_output_encoding(opts.format, opts.read.output_encoding)?;
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let headers = csv_headers(&mut reader)?;
+    let index = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow!("Column `{}` not found in the input", name))
+    };
+
+    let keys = opts
+        .group_by
+        .iter()
+        .map(|name| index(name))
+        .collect::<Result<Vec<_>>>()?;
+    let mut metrics = Vec::new();
+    for (names, agg) in [
+        (&opts.sum, Agg::Sum),
+        (&opts.avg, Agg::Avg),
+        (&opts.min, Agg::Min),
+        (&opts.max, Agg::Max),
+    ] {
+        for name in names {
+            metrics.push((agg, index(name)?, format!("{}({})", agg.name(), name)));
+        }
+    }
+    // 同名的输出列会互相覆盖, 例如 --group-by 一个叫 count 的列同时 --count
+    let mut outputs: Vec<&str> = opts.group_by.iter().map(String::as_str).collect();
+    if opts.count {
+        outputs.push("count");
+    }
+    outputs.extend(metrics.iter().map(|(_, _, name)| name.as_str()));
+    for (i, name) in outputs.iter().enumerate() {
+        if outputs[..i].contains(name) {
+            bail!("Output column `{}` would be written more than once", name);
+        }
+    }
+    let filter = opts
+        .filter
+        .as_deref()
+        .map(|expr| Filter::parse(expr, &headers))
+        .transpose()?;
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut lookup: HashMap<Vec<String>, usize> = HashMap::new();
+    if keys.is_empty() {
+        // 没有 --group-by 时整个文件是一组, 空文件也输出一行
+        lookup.insert(Vec::new(), 0);
+        groups.push(Group {
+            key: Vec::new(),
+            count: 0,
+            accs: vec![Acc::default(); metrics.len()],
+        });
+    }
+
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if filter.as_ref().is_some_and(|f| !f.matches(&record)) {
+            continue;
+        }
+        let key: Vec<String> = keys
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default().to_string())
+            .collect();
+        let idx = match lookup.get(&key) {
+            Some(&idx) => idx,
+            None => {
+                groups.push(Group {
+                    key: key.clone(),
+                    count: 0,
+                    accs: vec![Acc::default(); metrics.len()],
+                });
+                lookup.insert(key, groups.len() - 1);
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[idx];
+        group.count += 1;
+        for ((_, i, _), acc) in metrics.iter().zip(&mut group.accs) {
+            acc.update(record.get(*i).unwrap_or_default())
+                .map_err(|e| {
+                    let line = record.position().map_or(1, |p| p.line());
+                    anyhow!("line {}: column `{}`: {}", line, &headers[*i], e)
+                })?;
+        }
+    }
+
+    let mut output = record_writer(writer, opts.format);
+    for group in &groups {
+        let mut row = Map::new();
+        for (name, value) in opts.group_by.iter().zip(&group.key) {
+            row.insert(name.clone(), Value::String(value.clone()));
+        }
+        if opts.count {
+            row.insert("count".to_string(), Value::from(group.count));
+        }
+        for ((agg, _, name), acc) in metrics.iter().zip(&group.accs) {
+            row.insert(name.clone(), acc.value(*agg));
+        }
+        output.write_record(&Value::Object(row))?;
+    }
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+    use clap::Parser;
+    use serde_json::json;
+
+    fn agg(reader: &mut dyn Read, args: &[&str]) -> Result<Value> {
+        let opts = CsvAggOps::parse_from(["agg"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_agg(reader, &mut buf, &opts)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    #[test]
+    fn test_process_csv_agg() -> Result<()> {
+        let mut reader = get_reader("assets/juventus.csv")?;
+        let args = [
+            "--group-by",
+            "Nationality",
+            "--count",
+            "--avg",
+            "Kit Number",
+            "--max",
+            "Kit Number",
+        ];
+        let ret = agg(&mut reader, &args)?;
+        let groups = ret.as_array().unwrap();
+        assert_eq!(groups.len(), 14);
+        assert_eq!(
+            groups[0],
+            json!({"Nationality": "Poland", "count": 1, "avg(Kit Number)": 1.0, "max(Kit Number)": 1})
+        );
+        let total: u64 = groups.iter().map(|g| g["count"].as_u64().unwrap()).sum();
+        assert_eq!(total, 27);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_agg_without_group() -> Result<()> {
+        let input = "team,goals,rating\na,3,6.5\nb,,7\na,2,\n";
+        let args = ["--count", "--sum", "goals,rating", "--min", "rating"];
+        let ret = agg(&mut input.as_bytes(), &args)?;
+        assert_eq!(
+            ret,
+            json!([{"count": 3, "sum(goals)": 5, "sum(rating)": 13.5, "min(rating)": 6.5}])
+        );
+
+        let ret = agg(
+            &mut input.as_bytes(),
+            &["--count", "--where", "team == 'c'"],
+        )?;
+        assert_eq!(ret, json!([{"count": 0}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_agg_large_integers() -> Result<()> {
+        let input = "id\n9007199254740993\n1\n";
+        let ret = agg(&mut input.as_bytes(), &["--sum", "id", "--max", "id"])?;
+        assert_eq!(
+            ret,
+            json!([{"sum(id)": 9007199254740994_i64, "max(id)": 9007199254740993_i64}])
+        );
+
+        let input = "id\n9223372036854775807\n1\n";
+        let ret = agg(&mut input.as_bytes(), &["--sum", "id", "--max", "id"])?;
+        assert_eq!(
+            ret,
+            json!([{"sum(id)": 9223372036854775808.0, "max(id)": 9223372036854775807_i64}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_agg_errors() {
+        let input = "team,goals\na,3\nb,many\n";
+        assert!(agg(&mut input.as_bytes(), &["--sum", "assists"]).is_err());
+        let err = agg(&mut input.as_bytes(), &["--sum", "goals"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3: column `goals`: `many` is not a number"
+        );
+        let input = "count,goals\na,3\n";
+        let err = agg(&mut input.as_bytes(), &["--group-by", "count", "--count"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Output column `count` would be written more than once"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::FileWriter;
+use arrow_json::reader::{infer_json_schema_from_iterator, Decoder, ReaderBuilder};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde_json::Value;
+
+use super::csv_output::RecordWriter;
+use crate::cli::OutputFormat;
+use crate::FinishWrite;
+
+// 列类型由前 BATCH_ROWS 条记录推断, 之后每攒够这么多条写出一批
+const BATCH_ROWS: usize = 1024;
+
+/// Writes records as Parquet or an Arrow IPC file.
+///
+/// Column types are inferred from the first batch of records, later records that do
+/// not fit them are an error.
+pub struct ColumnarWriter<W: FinishWrite> {
+    format: OutputFormat,
+    writer: Option<W>,
+    rows: Vec<Value>,
+    written: usize,
+    sink: Option<(Decoder, Sink<W>)>,
+}
+
+enum Sink<W: FinishWrite> {
+    // parquet 只接受 Send 的 writer, 先写进共享缓冲区再转给 W
+    Parquet(ArrowWriter<SharedBuffer>, SharedBuffer, W),
+    Arrow(FileWriter<W>),
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().expect("not poisoned").extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn drain_into(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut buf = self.0.lock().expect("not poisoned");
+        writer.write_all(&buf)?;
+        buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> ColumnarWriter<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        Self {
+            format,
+            writer: Some(writer),
+            rows: Vec::with_capacity(BATCH_ROWS),
+            written: 0,
+            sink: None,
+        }
+    }
+
+    fn start(&mut self) -> Result<(Decoder, Sink<W>)> {
+        let schema = Arc::new(infer_schema(&self.rows)?);
+        let decoder = ReaderBuilder::new(schema.clone())
+            .with_batch_size(BATCH_ROWS)
+            .with_coerce_primitive(true)
+            .build_decoder()?;
+        let writer = self.writer.take().expect("started once");
+        let sink = match self.format {
+            OutputFormat::Parquet => {
+                let buf = SharedBuffer::default();
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                let inner = ArrowWriter::try_new(buf.clone(), schema, Some(props))?;
+                Sink::Parquet(inner, buf, writer)
+            }
+            _ => Sink::Arrow(FileWriter::try_new(writer, &schema)?),
+        };
+        Ok((decoder, sink))
+    }
+
+    fn write_rows(&mut self) -> Result<()> {
+        if self.sink.is_none() {
+            self.sink = Some(self.start()?);
+        }
+        let (decoder, sink) = self.sink.as_mut().expect("started above");
+        let first = self.written + 1;
+        let batch = decoder.serialize(&self.rows).and_then(|_| decoder.flush());
+        let batch = batch.map_err(|e| {
+            anyhow!(
+                "records {}-{} do not fit the column types inferred from the first {}: {}",
+                first,
+                first + self.rows.len() - 1,
+                BATCH_ROWS,
+                e
+            )
+        })?;
+        if let Some(batch) = batch {
+            sink.write(&batch)?;
+        }
+        self.written += self.rows.len();
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> Sink<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            Sink::Parquet(inner, buf, writer) => {
+                inner.write(batch)?;
+                buf.drain_into(writer)?;
+            }
+            Sink::Arrow(inner) => inner.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self {
+            Sink::Parquet(inner, buf, writer) => {
+                inner.finish()?;
+                buf.drain_into(writer)?;
+                writer.finish()?;
+            }
+            Sink::Arrow(inner) => {
+                inner.finish()?;
+                inner.get_mut().finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for ColumnarWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        self.rows.push(record.clone());
+        if self.rows.len() == BATCH_ROWS {
+            self.write_rows()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // 没有记录时也要写出一个空 schema 的文件
+        if !self.rows.is_empty() || self.sink.is_none() {
+            self.write_rows()?;
+        }
+        let (_, sink) = self.sink.as_mut().expect("started above");
+        sink.finish()
+    }
+}
+
+/// Column types of the records, in the order the columns first appear. String
+/// columns holding nothing but `YYYY-MM-DD` dates become dates.
+fn infer_schema(rows: &[Value]) -> Result<Schema> {
+    let inferred = infer_json_schema_from_iterator(rows.iter().map(Ok))?;
+    let mut names: Vec<&str> = Vec::new();
+    for row in rows {
+        let Value::Object(map) = row else {
+            return Err(anyhow!("Only records can be written as parquet or arrow"));
+        };
+        for name in map.keys() {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+    }
+
+    let fields = names
+        .into_iter()
+        .map(|name| {
+            let field = inferred.field_with_name(name)?;
+            let is_date = |row: &Value| match &row[name] {
+                Value::Null => true,
+                Value::String(s) => NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+                _ => false,
+            };
+            let any_date = rows.iter().any(|row| row[name].is_string());
+            if field.data_type() == &DataType::Utf8 && any_date && rows.iter().all(is_date) {
+                Ok(Field::new(name, DataType::Date32, true))
+            } else {
+                Ok(field.clone())
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Schema::new(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{cast::AsArray, types::Date32Type, Array};
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+
+    fn players(n: usize) -> Vec<Value> {
+        (0..n)
+            .map(|i| {
+                json!({
+                    "Name": format!("Player {}", i),
+                    "Kit Number": i,
+                    "Rating": if i % 2 == 0 { json!(7) } else { json!(6.5) },
+                    "DOB": if i == 0 { Value::Null } else { json!("1990-04-18") },
+                    "Note": if i == 1 { json!(12) } else { json!("-") },
+                })
+            })
+            .collect()
+    }
+
+    fn write_all(format: OutputFormat, records: &[Value]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut writer = ColumnarWriter::new(&mut buf, format);
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(buf)
+    }
+
+    fn check(batches: &[RecordBatch], rows: usize) {
+        let schema = batches[0].schema();
+        let types: Vec<_> = schema.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(
+            types,
+            [
+                &DataType::Utf8,
+                &DataType::Int64,
+                &DataType::Float64,
+                &DataType::Date32,
+                &DataType::Utf8
+            ]
+        );
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), rows);
+        let dob = batches[0].column(3).as_primitive::<Date32Type>();
+        assert!(dob.is_null(0));
+        assert_eq!(dob.value_as_date(1), NaiveDate::from_ymd_opt(1990, 4, 18));
+        assert_eq!(batches[0].column(4).as_string::<i32>().value(1), "12");
+    }
+
+    #[test]
+    fn test_parquet_writer() -> Result<()> {
+        let buf = write_all(OutputFormat::Parquet, &players(BATCH_ROWS + 10))?;
+        let mut file = tempfile::tempfile()?;
+        file.write_all(&buf)?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        check(&batches, BATCH_ROWS + 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_writer() -> Result<()> {
+        let buf = write_all(OutputFormat::Arrow, &players(3))?;
+        let reader = FileReader::try_new(io::Cursor::new(buf), None)?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        check(&batches, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_columnar_writer_type_mismatch() {
+        let mut records = players(BATCH_ROWS);
+        records.push(json!({"Name": "Buffon", "Kit Number": "seventy-seven"}));
+        let err = write_all(OutputFormat::Parquet, &records).unwrap_err();
+        assert!(err.to_string().starts_with("records 1025-1025 do not fit"));
+    }
+}
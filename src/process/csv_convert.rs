@@ -1,53 +1,918 @@
-use anyhow::Result;
-use csv::Reader;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Read, Write},
+};
 
-use crate::cli::OutputFormat;
+use anyhow::{anyhow, bail, Result};
+use csv::{Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use encoding_rs::UTF_8;
+use serde_json::{Map, Value};
+use tempfile::TempDir;
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    #[serde(rename = "Name")]
+use super::csv_encoding::{check_output_encoding, decode_reader};
+use super::csv_filter::Filter;
+use super::csv_infer::{infer_value, parse_value};
+use super::csv_output::{delimited_writer, record_writer, RecordWriter};
+use super::csv_parallel::{process_csv_parallel, CHUNK_BYTES};
+use super::csv_sort::process_csv_sort;
+use crate::cli::{CellType, CsvOps, CsvReadOps, CsvSortOps, InputEncoding, OutputFormat};
+use crate::FinishWrite;
+
+// 报告中最多列出的错误条数
+const MAX_REPORTED_ERRORS: usize = 20;
+// --split-by 排序时内存中最多保留的记录数, 超过后分段写到磁盘
+const SPLIT_BUFFER_ROWS: usize = 100_000;
+const SPLIT_NAME_PREFIX: &str = "file:";
+
+/// CSV reader of the input transcoded to UTF-8 per `--encoding`.
+pub fn csv_reader<'a>(
+    reader: impl Read + 'a,
+    ops: &CsvReadOps,
+) -> Result<Reader<Box<dyn Read + 'a>>> {
+    let reader = decode_reader(reader, ops.encoding)?;
+    Ok(reader_builder(ops).from_reader(reader))
+}
+
+pub(super) fn reader_builder(ops: &CsvReadOps) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .has_headers(ops.header)
+        .delimiter(ops.delimiter)
+        .quote(ops.quote)
+        .escape(ops.escape)
+        .comment(ops.comment);
+    builder
+}
+
+pub fn csv_writer<W: Write>(writer: W, ops: &CsvReadOps) -> Writer<W> {
+    WriterBuilder::new()
+        .delimiter(ops.delimiter)
+        .quote(ops.quote)
+        .escape(ops.escape.unwrap_or(b'\\'))
+        .double_quote(ops.escape.is_none())
+        .comment(ops.comment)
+        .from_writer(writer)
+}
+
+/// Column names of the input, `column_1..N` when it has no header row.
+pub fn csv_headers<R: Read>(reader: &mut Reader<R>) -> Result<StringRecord> {
+    // 没有表头时 headers() 返回第一行记录(不会被消费), 仅用来确定列数
+    let has_headers = reader.has_headers();
+    let headers = reader.headers()?;
+    if has_headers {
+        return Ok(headers.clone());
+    }
+    Ok((1..=headers.len())
+        .map(|i| format!("column_{}", i))
+        .collect())
+}
+
+/// Turns raw CSV records into JSON objects: filters them with `--where`, keeps the
+/// selected columns under their new names and types cells per `--infer` / `--schema`.
+pub struct RecordConverter {
+    columns: Vec<Column>,
+    filter: Option<Filter>,
+    infer: bool,
+    unflatten: bool,
+}
+
+struct Column {
+    index: usize,
     name: String,
-    // #[serde(rename = "Position")]
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    // #[serde(rename = "Nationality")]
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
+    ty: Option<CellType>,
+    // --unflatten 时列名拆成的路径
+    path: Vec<Segment>,
+}
+
+// --unflatten 时数组下标的上限
+const MAX_INDEX: usize = 9999;
+
+/// One step of a nested column name: `tags[0]` is `Key("tags"), Index(0)`.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
 }
 
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
+impl RecordConverter {
+    pub fn try_new(headers: StringRecord, opts: &CsvOps) -> Result<Self> {
+        let position = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("Column `{}` not found in the input", name))
+        };
+
+        let indices = if opts.select.is_empty() {
+            (0..headers.len()).collect()
+        } else {
+            opts.select
+                .iter()
+                .map(|name| position(name))
+                .collect::<Result<Vec<_>>>()?
+        };
+        let excluded = opts
+            .exclude
+            .iter()
+            .map(|name| position(name))
+            .collect::<Result<Vec<_>>>()?;
+        let mut columns: Vec<Column> = indices
+            .into_iter()
+            .filter(|idx| !excluded.contains(idx))
+            .map(|index| Column {
+                index,
+                name: headers[index].to_string(),
+                ty: None,
+                path: Vec::new(),
+            })
+            .collect();
+
+        // schema 和 rename 都使用原始列名
+        for (name, ty) in &opts.schema {
+            let idx = position(name)
+                .map_err(|_| anyhow!("Schema column `{}` not found in the input", name))?;
+            columns
+                .iter_mut()
+                .filter(|c| c.index == idx)
+                .for_each(|c| c.ty = Some(*ty));
+        }
+        for (old, new) in &opts.rename {
+            let idx = position(old)?;
+            columns
+                .iter_mut()
+                .filter(|c| c.index == idx)
+                .for_each(|c| c.name = new.clone());
+        }
+        // 同名的输出列会互相覆盖, 丢掉前面的值
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].iter().any(|c| c.name == column.name) {
+                bail!("duplicate output column `{}`", column.name);
+            }
+        }
+
+        if opts.unflatten {
+            // 先用占位值建一遍, 提前发现 `a` 和 `a.b` 这样冲突的列名
+            let mut skeleton = Value::Null;
+            for column in &mut columns {
+                column.path = parse_path(&column.name, &opts.separator);
+                // 下标决定数组长度, 不能任由列名申请内存
+                if column
+                    .path
+                    .iter()
+                    .any(|s| matches!(s, Segment::Index(i) if *i > MAX_INDEX))
+                {
+                    bail!(
+                        "Index too large in column `{}`, at most {} is supported",
+                        column.name,
+                        MAX_INDEX
+                    );
+                }
+                if !insert_path(&mut skeleton, &column.path, Value::Bool(true)) {
+                    bail!(
+                        "Column `{}` conflicts with another column when unflattened",
+                        column.name
+                    );
+                }
+            }
+        }
+
+        let filter = match &opts.filter {
+            Some(expr) => Some(Filter::parse(expr, &headers)?),
+            None => None,
+        };
+        Ok(Self {
+            columns,
+            filter,
+            unflatten: opts.unflatten,
+            // parquet / arrow 的列类型来自单元格的值, 总是推断
+            infer: opts.infer || matches!(opts.format, OutputFormat::Parquet | OutputFormat::Arrow),
+        })
+    }
+
+    /// Names of the output columns.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|c| c.name.as_str())
+    }
 
-    // for result in reader.deserialize() {
-    //      let record: Player = result?;
+    /// Convert one record, `None` when `--where` filters it out. Fails with every
+    /// cell that does not match its pinned type.
+    pub fn convert(&self, record: &StringRecord) -> Result<Option<Value>> {
+        if let Some(filter) = &self.filter {
+            if !filter.matches(record) {
+                return Ok(None);
+            }
+        }
 
-    //     ret.push(record);
+        let mut ret = Map::with_capacity(self.columns.len());
+        let mut nested = Value::Object(Map::new());
+        let mut errors = Vec::new();
+        for column in &self.columns {
+            let field = record.get(column.index).unwrap_or_default();
+            let value = match column.ty {
+                Some(ty) => match parse_value(field, ty) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        errors.push(format!("column `{}`: {}", column.name, e));
+                        continue;
+                    }
+                },
+                None if self.infer => infer_value(field),
+                None => Value::String(field.to_string()),
+            };
+            if !self.unflatten {
+                ret.insert(column.name.clone(), value);
+            } else if column.path.len() == 1 || !field.is_empty() {
+                // 嵌套的空单元格不输出, 没有值的行不会得到空对象或空数组
+                insert_path(&mut nested, &column.path, value);
+            }
+        }
 
-    // }
+        if !errors.is_empty() {
+            let line = record.position().map_or(0, |p| p.line());
+            bail!("line {}: {}", line, errors.join(", "));
+        }
+        if self.unflatten {
+            return Ok(Some(nested));
+        }
+        Ok(Some(Value::Object(ret)))
+    }
+}
+
+/// Split a column name on `separator` and `[n]` suffixes, the reverse of `csv from`.
+fn parse_path(name: &str, separator: &str) -> Vec<Segment> {
+    let mut path = Vec::new();
+    for part in name.split(separator) {
+        let mut key = part;
+        let mut indices = Vec::new();
+        // 从后往前剥掉 [n], `a[x]` 这样的不算下标
+        while let Some(rest) = key.strip_suffix(']') {
+            let Some((head, index)) = rest.rsplit_once('[') else {
+                break;
+            };
+            let Ok(index) = index.parse() else {
+                break;
+            };
+            indices.push(Segment::Index(index));
+            key = head;
+        }
+        if !key.is_empty() || indices.is_empty() {
+            path.push(Segment::Key(key.to_string()));
+        }
+        path.extend(indices.into_iter().rev());
+    }
+    path
+}
 
-    let headers = reader.headers()?.clone(); //读取表头
-    for result in reader.records() {
-        let record = result?;
-        // zip 将两个迭代器合并为元组迭代器[(header, record),...]
-        // collect 将元组迭代器转换为 Vec<(header, record)>
+/// Put `value` at `path` in `target`, creating objects and arrays on the way. Fails
+/// when something else is already there.
+fn insert_path(target: &mut Value, path: &[Segment], value: Value) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        if !target.is_null() {
+            return false;
+        }
+        *target = value;
+        return true;
+    };
+    match first {
+        Segment::Key(key) => {
+            if target.is_null() {
+                *target = Value::Object(Map::new());
+            }
+            let Value::Object(map) = target else {
+                return false;
+            };
+            insert_path(map.entry(key.clone()).or_insert(Value::Null), rest, value)
+        }
+        Segment::Index(i) => {
+            if target.is_null() {
+                *target = Value::Array(Vec::new());
+            }
+            let Value::Array(items) = target else {
+                return false;
+            };
+            if items.len() <= *i {
+                items.resize(i + 1, Value::Null);
+            }
+            insert_path(&mut items[*i], rest, value)
+        }
+    }
+}
 
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
+pub fn process_csv(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvOps,
+) -> Result<()> {
+    check_output_encoding(opts.format, opts.read.output_encoding)?;
+    if opts.threads != 1 {
+        return process_csv_parallel(reader, writer, opts, CHUNK_BYTES);
     }
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let mut output = output_writer(writer, opts);
+    let headers = csv_headers(&mut reader)?; //读取表头
+    let errors = convert_each(&mut reader, headers, opts, |_, value| {
+        output.write_record(value)
+    })?;
+    output.finish()?;
+
+    report_errors(&errors)
+}
+
+/// Convert into several outputs: a new one every `--split-rows` rows, or one per
+/// distinct value of the `--split-by` column.
+///
+/// `template` is the output name, `{n}` is replaced by the 1-based file number and
+/// `{key}` by the column value. `create` opens the output of a name.
+///
+/// With `--split-by` the records are first sorted by output name through temporary
+/// files, so only one output is open at a time however many values the column has.
+/// Distinct values that would share a file, also when the names only differ in case,
+/// are an error.
+pub fn process_csv_split(
+    reader: &mut dyn Read,
+    opts: &CsvOps,
+    template: &str,
+    create: &mut dyn FnMut(&str) -> Result<Box<dyn FinishWrite>>,
+) -> Result<()> {
+    check_output_encoding(opts.format, opts.read.output_encoding)?;
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let headers = csv_headers(&mut reader)?;
+    let mut open =
+        |name: &str| -> Result<Box<dyn RecordWriter>> { Ok(output_writer(create(name)?, opts)) };
+
+    let errors = match (opts.split_rows, &opts.split_by) {
+        (Some(rows), _) => {
+            if rows == 0 {
+                bail!("--split-rows must be greater than 0");
+            }
+            if !template.contains("{n}") {
+                bail!(
+                    "Output name `{}` must contain {{n}} with --split-rows",
+                    template
+                );
+            }
+            let mut output: Option<Box<dyn RecordWriter>> = None;
+            let mut count = 0;
+            let errors = convert_each(&mut reader, headers, opts, |_, value| {
+                if count % rows == 0 {
+                    if let Some(mut output) = output.take() {
+                        output.finish()?;
+                    }
+                    let name = template.replace("{n}", &(count / rows + 1).to_string());
+                    output = Some(open(&name)?);
+                }
+                count += 1;
+                output.as_mut().expect("opened above").write_record(value)
+            })?;
+            if let Some(mut output) = output {
+                output.finish()?;
+            }
+            errors
+        }
+        (None, Some(column)) => {
+            if !template.contains("{key}") {
+                bail!(
+                    "Output name `{}` must contain {{key}} with --split-by",
+                    template
+                );
+            }
+            let idx = headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| anyhow!("Column `{}` not found in the input", column))?;
+            // 转换后的记录连同文件名先写到临时文件, 按文件名排序后同一个文件的记录相邻,
+            // 任何时候只打开一个输出, 不受不同值个数的限制
+            let dir = TempDir::new()?;
+            let spilled = dir.path().join("split.csv");
+            let mut spill = Writer::from_path(&spilled)?;
+            spill.write_record(["name", "file", "key", "value"])?;
+            let errors = convert_each(&mut reader, headers, opts, |record, value| {
+                let key = record.get(idx).unwrap_or_default();
+                let file = template.replace("{key}", &file_key(key));
+                // 按小写的文件名排序, 在不区分大小写的文件系统上会撞名的值也相邻;
+                // 加上前缀, 文件名总是按文本比较, 而不是 10 和 10.0 当成同一个数
+                let name = format!("{}{}", SPLIT_NAME_PREFIX, file.to_lowercase());
+                spill.write_record([name, file, key.to_string(), value.to_string()])?;
+                Ok(())
+            })?;
+            spill.flush()?;
+            drop(spill);
 
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+            let sorted = dir.path().join("split.sorted.csv");
+            let mut writer = BufWriter::new(File::create(&sorted)?);
+            process_csv_sort(&mut File::open(&spilled)?, &mut writer, &split_sort_ops())?;
+            // 当前输出的排序名, 列值和 writer
+            let mut output: Option<(String, String, Box<dyn RecordWriter>)> = None;
+            for record in Reader::from_path(&sorted)?.into_records() {
+                let record = record?;
+                let (name, file, key) = (&record[0], &record[1], &record[2]);
+                let value: Value = serde_json::from_str(&record[3])?;
+                match &output {
+                    Some((current, current_key, _)) if current == name => {
+                        if current_key != key {
+                            bail!(
+                                "Values `{}` and `{}` of column `{}` would both be written to `{}`",
+                                current_key,
+                                key,
+                                column,
+                                file
+                            );
+                        }
+                    }
+                    _ => {
+                        if let Some((_, _, mut output)) = output.take() {
+                            output.finish()?;
+                        }
+                        output = Some((name.to_string(), key.to_string(), open(file)?));
+                    }
+                }
+                output
+                    .as_mut()
+                    .expect("opened above")
+                    .2
+                    .write_record(&value)?;
+            }
+            if let Some((_, _, mut output)) = output {
+                output.finish()?;
+            }
+            errors
+        }
+        (None, None) => bail!("--split-rows or --split-by is required"),
     };
 
-    // let json = serde_json::to_string_pretty(&ret)?;
-    std::fs::write(output, content)?;
+    report_errors(&errors)
+}
+
+pub(super) fn output_writer<'a>(
+    writer: impl FinishWrite + 'a,
+    opts: &CsvOps,
+) -> Box<dyn RecordWriter + 'a> {
+    match opts.output_delimiter {
+        Some(d) if matches!(opts.format, OutputFormat::Csv | OutputFormat::Tsv) => {
+            delimited_writer(writer, d)
+        }
+        _ => record_writer(writer, opts.format),
+    }
+}
+
+/// Convert every record and hand it to `write` along with the raw record, returning
+/// the records that failed to convert.
+fn convert_each<R: Read>(
+    reader: &mut Reader<R>,
+    headers: StringRecord,
+    opts: &CsvOps,
+    mut write: impl FnMut(&StringRecord, &Value) -> Result<()>,
+) -> Result<RecordErrors> {
+    let converter = RecordConverter::try_new(headers, opts)?;
+
+    // 复用同一个 record, 逐条转换并写出, 不在内存中保留全部记录
+    let mut record = StringRecord::new();
+    let mut errors = RecordErrors::default();
+    while reader.read_record(&mut record)? {
+        match converter.convert(&record) {
+            Ok(Some(value)) => write(&record, &value)?,
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok(errors)
+}
+
+/// A column value usable in a file name, path separators and the like become `_`.
+fn file_key(value: &str) -> String {
+    if value.is_empty() {
+        return "empty".to_string();
+    }
+    // `.` 和 `..` 会指向当前或上级目录
+    if value.chars().all(|c| c == '.') {
+        return "_".repeat(value.len());
+    }
+    value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_. ".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Sort the spilled `name,file,key,value` rows of `--split-by` by name, keeping the
+/// input order within a name.
+fn split_sort_ops() -> CsvSortOps {
+    CsvSortOps {
+        input: "-".to_string(),
+        output: None,
+        by: vec!["name".to_string()],
+        desc: false,
+        dedup_by: Vec::new(),
+        buffer_rows: SPLIT_BUFFER_ROWS,
+        temp_dir: None,
+        read: CsvReadOps {
+            header: true,
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            encoding: InputEncoding::Label(UTF_8),
+            output_encoding: UTF_8,
+            sheet: None,
+        },
+    }
+}
+
+/// Records that failed to convert: the first `MAX_REPORTED_ERRORS` messages and how
+/// many failed in all, so memory does not grow with the number of bad records.
+#[derive(Debug, Default)]
+pub(super) struct RecordErrors {
+    messages: Vec<String>,
+    count: usize,
+}
 
-    Ok(())
+impl RecordErrors {
+    pub(super) fn push(&mut self, error: impl fmt::Display) {
+        self.count += 1;
+        if self.messages.len() < MAX_REPORTED_ERRORS {
+            self.messages.push(error.to_string());
+        }
+    }
+
+    /// Add the errors of a later part of the input.
+    pub(super) fn append(&mut self, other: RecordErrors) {
+        let room = MAX_REPORTED_ERRORS - self.messages.len();
+        self.messages.extend(other.messages.into_iter().take(room));
+        self.count += other.count;
+    }
+}
+
+/// Fail with a summary of the records that could not be converted.
+pub(super) fn report_errors(errors: &RecordErrors) -> Result<()> {
+    if errors.count == 0 {
+        return Ok(());
+    }
+    let mut report = format!("{} record(s) do not match the schema:", errors.count);
+    for e in &errors.messages {
+        report.push_str("\n  ");
+        report.push_str(e);
+    }
+    if errors.count > errors.messages.len() {
+        report.push_str(&format!(
+            "\n  ... and {} more",
+            errors.count - errors.messages.len()
+        ));
+    }
+    Err(anyhow!(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+    use clap::Parser;
+    use serde_json::json;
+
+    fn csv_ops(args: &[&str]) -> CsvOps {
+        CsvOps::parse_from(["csv", "-i", "-"].iter().chain(args))
+    }
+
+    fn convert(input: &str, format: OutputFormat) -> Result<String> {
+        let mut reader = get_reader(input)?;
+        let mut buf = Vec::new();
+        let opts = csv_ops(&["--format", &format.to_string()]);
+        process_csv(&mut reader, &mut buf, &opts)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn convert_str(input: &str, args: &[&str]) -> Result<Value> {
+        let mut buf = Vec::new();
+        process_csv(&mut input.as_bytes(), &mut buf, &csv_ops(args))?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn load_all(input: &str) -> Result<Vec<Value>> {
+        let mut reader = Reader::from_path(input)?;
+        let headers = reader.headers()?.clone();
+        let mut ret = Vec::new();
+        for record in reader.records() {
+            ret.push(headers.iter().zip(record?.iter()).collect::<Value>());
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_process_csv_json_matches_buffered_output() -> Result<()> {
+        let input = "assets/juventus.csv";
+        let expected = serde_json::to_string_pretty(&load_all(input)?)?;
+        assert_eq!(convert(input, OutputFormat::Json)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_yaml_matches_buffered_output() -> Result<()> {
+        let input = "assets/juventus.csv";
+        let expected = serde_yaml::to_string(&load_all(input)?)?;
+        assert_eq!(convert(input, OutputFormat::Yaml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_empty_input() -> Result<()> {
+        let mut buf = Vec::new();
+        process_csv(&mut "a,b\n".as_bytes(), &mut buf, &csv_ops(&[]))?;
+        assert_eq!(buf, b"[]");
+        let mut buf = Vec::new();
+        let opts = csv_ops(&["--format", "yaml"]);
+        process_csv(&mut "a,b\n".as_bytes(), &mut buf, &opts)?;
+        assert_eq!(buf, b"[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_with_dialect() -> Result<()> {
+        let input = "# exported by vendor\nName;Note\n'Buffon';'it''s; fine'\n";
+        let ret = convert_str(input, &["-d", ";", "--quote", "'", "--comment", "#"])?;
+        assert_eq!(
+            ret,
+            serde_json::json!([{"Name": "Buffon", "Note": "it's; fine"}])
+        );
+
+        let ret = convert_str("a\tb\n1\t2,3\n", &["-d", "tab"])?;
+        assert_eq!(ret, serde_json::json!([{"a": "1", "b": "2,3"}]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_redelimit() -> Result<()> {
+        let input = "Name,Nationality\nPjanic,Bosnia-Herzegovina\nCan,Germany\n";
+        let mut buf = Vec::new();
+        let opts = csv_ops(&["--format", "csv", "--output-delimiter", ";"]);
+        process_csv(&mut input.as_bytes(), &mut buf, &opts)?;
+        assert_eq!(
+            buf,
+            b"Name;Nationality\nPjanic;Bosnia-Herzegovina\nCan;Germany\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_infer_types() -> Result<()> {
+        let input = "Name,Kit Number,Height,Captain,DOB,Note\nBuffon,1,1.92,true,1978-01-28,\n";
+        let ret = convert_str(input, &["--infer"])?;
+        assert_eq!(
+            ret,
+            serde_json::json!([{
+                "Name": "Buffon",
+                "Kit Number": 1,
+                "Height": 1.92,
+                "Captain": true,
+                "DOB": "1978-01-28",
+                "Note": null,
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_schema() -> Result<()> {
+        let input = "Name,Kit Number\nBuffon,01\n";
+        let ret = convert_str(input, &["--schema", "Kit Number=int,Name=string"])?;
+        assert_eq!(
+            ret,
+            serde_json::json!([{"Name": "Buffon", "Kit Number": 1}])
+        );
+
+        let input = "Name,Kit Number\nBuffon,1\nDanilo,n/a\nCan,\nDybala,x\n";
+        let err = convert_str(input, &["--schema", "Kit Number=int"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 record(s) do not match the schema:\n  \
+             line 3: column `Kit Number`: `n/a` is not a valid int\n  \
+             line 5: column `Kit Number`: `x` is not a valid int"
+        );
+
+        // only the first messages are kept, the rest are counted
+        let mut input = "Kit Number\n".to_string();
+        input.push_str(&"x\n".repeat(1000));
+        let err = convert_str(&input, &["--schema", "Kit Number=int"]).unwrap_err();
+        let lines: Vec<String> = err.to_string().lines().map(String::from).collect();
+        assert_eq!(lines[0], "1000 record(s) do not match the schema:");
+        assert_eq!(lines.len(), MAX_REPORTED_ERRORS + 2);
+        assert_eq!(lines.last().unwrap(), "  ... and 980 more");
+
+        let err = convert_str(&input, &["--schema", "Number=int"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Schema column `Number` not found in the input"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_shape_columns() -> Result<()> {
+        let mut reader = get_reader("assets/juventus.csv")?;
+        let mut buf = Vec::new();
+        let opts = csv_ops(&[
+            "--where",
+            r#"Position == "Goalkeeper" && Kit Number > 10"#,
+            "--select",
+            "Kit Number,Name,DOB",
+            "--exclude",
+            "DOB",
+            "--rename",
+            "Kit Number=kit",
+            "--schema",
+            "Kit Number=int",
+            "--format",
+            "csv",
+        ]);
+        process_csv(&mut reader, &mut buf, &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "kit,Name\n37,Mattia Perin\n77,Gianluigi Buffon\n31,Carlo Pinsoglio\n"
+        );
+
+        let err = convert_str("a,b\n1,2\n", &["--select", "c"]).unwrap_err();
+        assert_eq!(err.to_string(), "Column `c` not found in the input");
+        let err = convert_str("a,b\n1,2\n", &["--select", "a,b", "--rename", "b=a"]).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate output column `a`");
+        let err = convert_str("a,b\n1,2\n", &["--select", "a,a"]).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate output column `a`");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_unflatten() -> Result<()> {
+        // the output of `csv from` on nested records
+        let input = "Name,Kit Number,address.city,address.zip,tags[0],tags[1],Retired\n\
+                     Buffon,77,Turin,10151,,,\n\
+                     \"Dybala, Paulo\",,,,captain,10,false\n";
+        let ret = convert_str(input, &["--unflatten", "--infer"])?;
+        assert_eq!(
+            ret,
+            json!([
+                {"Name": "Buffon", "Kit Number": 77, "address": {"city": "Turin", "zip": 10151}, "Retired": null},
+                {"Name": "Dybala, Paulo", "Kit Number": null, "tags": ["captain", 10], "Retired": false}
+            ])
+        );
+
+        let ret = convert_str("a/b[1],a/c\nx,y\n", &["--unflatten", "--separator", "/"])?;
+        assert_eq!(ret, json!([{"a": {"b": [null, "x"], "c": "y"}}]));
+
+        for header in [
+            "tags[18446744073709551615]",
+            "tags[4000000000]",
+            "tags[10000]",
+        ] {
+            let input = format!("{}\nx\n", header);
+            let err = convert_str(&input, &["--unflatten"]).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Index too large in column `{}`, at most 9999 is supported",
+                    header
+                )
+            );
+        }
+        assert!(CsvOps::try_parse_from(["csv", "--unflatten", "--separator", ""]).is_err());
+
+        let err = convert_str("a,a.b\n1,2\n", &["--unflatten"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `a.b` conflicts with another column when unflattened"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_path() {
+        use Segment::*;
+        assert_eq!(
+            parse_path("matrix[0][1].x", "."),
+            [Key("matrix".into()), Index(0), Index(1), Key("x".into())]
+        );
+        assert_eq!(parse_path("a[x]", "."), [Key("a[x]".into())]);
+        assert_eq!(parse_path("[2]", "."), [Index(2)]);
+    }
+
+    #[test]
+    fn test_process_csv_without_header() -> Result<()> {
+        let ret = convert_str("Buffon,1\nChiellini,3\n", &["--header", "false"])?;
+        assert_eq!(
+            ret,
+            serde_json::json!([
+                {"column_1": "Buffon", "column_2": "1"},
+                {"column_1": "Chiellini", "column_2": "3"},
+            ])
+        );
+        Ok(())
+    }
+
+    fn split(args: &[&str], template: &str) -> Result<Vec<(String, String)>> {
+        let dir = tempfile::tempdir()?;
+        let template = dir.path().join(template);
+        let mut reader = get_reader("assets/juventus.csv")?;
+        process_csv_split(
+            &mut reader,
+            &csv_ops(args),
+            template.to_str().unwrap(),
+            &mut |name| crate::get_writer(name),
+        )?;
+        let mut files = std::fs::read_dir(dir.path())?
+            .map(|entry| {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                Ok((name, std::fs::read_to_string(entry.path())?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        files.sort();
+        Ok(files)
+    }
+
+    #[test]
+    fn test_process_csv_split_rows() -> Result<()> {
+        let files = split(
+            &["--format", "csv", "--split-rows", "10"],
+            "players-{n}.csv",
+        )?;
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["players-1.csv", "players-2.csv", "players-3.csv"]);
+        let lines: Vec<_> = files.iter().map(|(_, c)| c.lines().count()).collect();
+        // every file has its own header
+        assert_eq!(lines, [11, 11, 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_by() -> Result<()> {
+        let files = split(&["--split-by", "Position"], "{key}.json")?;
+        assert_eq!(files.len(), 10);
+        let (name, content) = &files[4];
+        assert_eq!(name, "Goalkeeper.json");
+        let players: Vec<Value> = serde_json::from_str(content)?;
+        assert_eq!(players.len(), 4);
+        assert!(players.iter().all(|p| p["Position"] == "Goalkeeper"));
+        // rows of a file keep their input order
+        let names: Vec<_> = players
+            .iter()
+            .map(|p| p["Name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Wojciech Szczesny",
+                "Mattia Perin",
+                "Gianluigi Buffon",
+                "Carlo Pinsoglio"
+            ]
+        );
+
+        assert_eq!(file_key("a/b:c"), "a_b_c");
+        assert_eq!(file_key(""), "empty");
+        assert_eq!(file_key(".."), "__");
+        assert_eq!(file_key(".5"), ".5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_errors() {
+        assert!(split(&["--split-rows", "10"], "players.json").is_err());
+        assert!(split(&["--split-rows", "0"], "{n}.json").is_err());
+        assert!(split(&["--split-by", "Position"], "{n}.json").is_err());
+        assert!(split(&["--split-by", "Club"], "{key}.json").is_err());
+    }
+
+    #[test]
+    fn test_process_csv_split_by_collision() -> Result<()> {
+        let split = |input: &str| -> Result<()> {
+            let dir = TempDir::new()?;
+            let template = dir.path().join("{key}.json");
+            let opts = csv_ops(&["--split-by", "Nationality"]);
+            process_csv_split(
+                &mut input.as_bytes(),
+                &opts,
+                template.to_str().unwrap(),
+                &mut |name| crate::get_writer(name),
+            )
+        };
+        split("Name,Nationality\nBuffon,Italy\nDybala,Argentina\nChiellini,Italy\n")?;
+        for (a, b) in [("a/b", "a:b"), ("a/b", "a?b"), ("Italy", "italy")] {
+            let input = format!(
+                "Name,Nationality\nBuffon,{}\nDybala,x\nChiellini,{}\n",
+                a, b
+            );
+            let err = split(&input).unwrap_err().to_string();
+            assert!(
+                err.starts_with(&format!(
+                    "Values `{}` and `{}` of column `Nationality`",
+                    a, b
+                )),
+                "{}",
+                err
+            );
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,294 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+use serde_json::{json, Map, Value};
+
+use super::csv_convert::{csv_headers, csv_reader};
+use super::csv_encoding::check_output_encoding;
+use super::csv_output::record_writer;
+use super::csv_show::render_table;
+use crate::cli::CsvDiffOps;
+use crate::FinishWrite;
+
+#[derive(Debug)]
+enum Change {
+    Added(Vec<String>, Map<String, Value>),
+    Removed(Vec<String>, Map<String, Value>),
+    // 每个改动的字段: 列名, 修改前, 修改后
+    Changed(Vec<String>, Vec<(String, Value, Value)>),
+}
+
+struct Side {
+    headers: StringRecord,
+    keys: Vec<usize>,
+}
+
+impl Side {
+    fn new(headers: StringRecord, names: &[String], file: &str) -> Result<Self> {
+        let keys = names
+            .iter()
+            .map(|name| {
+                headers
+                    .iter()
+                    .position(|h| h == name)
+                    .ok_or_else(|| anyhow!("Key column `{}` not found in the {} file", name, file))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { headers, keys })
+    }
+
+    fn key(&self, record: &StringRecord) -> Vec<String> {
+        self.keys
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default().to_string())
+            .collect()
+    }
+
+    fn value(&self, record: &StringRecord, name: &str) -> Value {
+        self.headers
+            .iter()
+            .position(|h| h == name)
+            .and_then(|i| record.get(i))
+            .map_or(Value::Null, |v| Value::String(v.to_string()))
+    }
+
+    fn row(&self, record: &StringRecord) -> Map<String, Value> {
+        self.headers
+            .iter()
+            .zip(record.iter())
+            .map(|(h, v)| (h.to_string(), Value::String(v.to_string())))
+            .collect()
+    }
+}
+
+/// Compare two versions of a CSV file row by row, matching rows on the `--key` columns.
+///
+/// The old file is indexed in memory and the new file is streamed. Added and changed
+/// rows are reported in the order of the new file, removed rows in the order of the old one.
+pub fn process_csv_diff(
+    old: &mut dyn Read,
+    new: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvDiffOps,
+) -> Result<()> {
+    if let Some(format) = opts.format {
+        check_output_encoding(format, opts.read.output_encoding)?;
+    }
+    let changes = diff(old, new, opts)?;
+    match opts.format {
+        Some(format) => {
+            let mut output = record_writer(writer, format);
+            for change in &changes {
+                output.write_record(&patch(change, &opts.key))?;
+            }
+            output.finish()
+        }
+        None => {
+            let ret = render_changes(&changes, &opts.key);
+            writer.write_all(ret.as_bytes())?;
+            writer.finish()?;
+            Ok(())
+        }
+    }
+}
+
+fn diff(old: &mut dyn Read, new: &mut dyn Read, opts: &CsvDiffOps) -> Result<Vec<Change>> {
+    let mut old = csv_reader(old, &opts.read)?;
+    let mut new = csv_reader(new, &opts.read)?;
+    let old_side = Side::new(csv_headers(&mut old)?, &opts.key, "old")?;
+    let new_side = Side::new(csv_headers(&mut new)?, &opts.key, "new")?;
+
+    // 旧文件的行按 key 索引, 值为 (行, 是否在新文件中出现过)
+    let mut rows = Vec::new();
+    let mut index = HashMap::new();
+    for record in old.records() {
+        let record = record?;
+        let key = old_side.key(&record);
+        if index.insert(key.clone(), rows.len()).is_some() {
+            bail!("Duplicate key {:?} in the old file", key);
+        }
+        rows.push((record, false));
+    }
+
+    // 两个文件所有的列, 旧文件的列在前
+    let mut columns: Vec<String> = old_side.headers.iter().map(String::from).collect();
+    for h in new_side.headers.iter() {
+        if !columns.iter().any(|c| c == h) {
+            columns.push(h.to_string());
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut seen = HashSet::new();
+    for record in new.records() {
+        let record = record?;
+        let key = new_side.key(&record);
+        if !seen.insert(key.clone()) {
+            bail!("Duplicate key {:?} in the new file", key);
+        }
+        let Some(&i) = index.get(&key) else {
+            changes.push(Change::Added(key, new_side.row(&record)));
+            continue;
+        };
+        let (before, matched) = &mut rows[i];
+        *matched = true;
+        let fields: Vec<_> = columns
+            .iter()
+            .map(|c| {
+                (
+                    c.clone(),
+                    old_side.value(before, c),
+                    new_side.value(&record, c),
+                )
+            })
+            // 只在一边存在的列当作空值, 新增一个空列不算改动
+            .filter(|(_, a, b)| a.as_str().unwrap_or_default() != b.as_str().unwrap_or_default())
+            .collect();
+        if !fields.is_empty() {
+            changes.push(Change::Changed(key, fields));
+        }
+    }
+    for (record, _) in rows.iter().filter(|(_, matched)| !matched) {
+        changes.push(Change::Removed(old_side.key(record), old_side.row(record)));
+    }
+    Ok(changes)
+}
+
+/// One record per change, e.g. `{"op": "replace", "key": {...}, "fields": {"a": {"old": .., "new": ..}}}`.
+fn patch(change: &Change, key_names: &[String]) -> Value {
+    let key = |key: &[String]| -> Map<String, Value> {
+        key_names
+            .iter()
+            .zip(key)
+            .map(|(name, v)| (name.clone(), Value::String(v.clone())))
+            .collect()
+    };
+    match change {
+        Change::Added(k, row) => json!({"op": "add", "key": key(k), "value": row}),
+        Change::Removed(k, row) => json!({"op": "remove", "key": key(k), "value": row}),
+        Change::Changed(k, fields) => {
+            let fields: Map<String, Value> = fields
+                .iter()
+                .map(|(c, a, b)| (c.clone(), json!({"old": a, "new": b})))
+                .collect();
+            json!({"op": "replace", "key": key(k), "fields": fields})
+        }
+    }
+}
+
+/// A table with one line per added or removed row and per changed field.
+fn render_changes(changes: &[Change], key_names: &[String]) -> String {
+    let mut headers = vec!["change".to_string()];
+    headers.extend(key_names.iter().cloned());
+    headers.extend(["column", "old", "new"].map(String::from));
+
+    let text = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        _ => String::new(),
+    };
+    let line = |change: &str, key: &[String], rest: [String; 3]| {
+        let mut row = vec![change.to_string()];
+        row.extend(key.iter().cloned());
+        row.extend(rest);
+        row
+    };
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    let mut rows = Vec::new();
+    for change in changes {
+        match change {
+            Change::Added(key, _) => {
+                added += 1;
+                rows.push(line("added", key, Default::default()));
+            }
+            Change::Removed(key, _) => {
+                removed += 1;
+                rows.push(line("removed", key, Default::default()));
+            }
+            Change::Changed(key, fields) => {
+                changed += 1;
+                for (c, a, b) in fields {
+                    rows.push(line("changed", key, [c.clone(), text(a), text(b)]));
+                }
+            }
+        }
+    }
+
+    let mut ret = render_table(&headers, &rows, usize::MAX);
+    ret.push_str(&format!(
+        "{} added, {} removed, {} changed\n",
+        added, removed, changed
+    ));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const OLD: &str = "Name,Position,Kit Number\n\
+                       Paulo Dybala,Second Striker,21\n\
+                       Mattia Perin,Goalkeeper,37\n\
+                       Blaise Matuidi,Central Midfield,14\n";
+    const NEW: &str = "Name,Position,Kit Number,Note\n\
+                       Mattia Perin,Goalkeeper,37,\n\
+                       Paulo Dybala,Second Striker,10,captain\n\
+                       Moise Kean,Centre-Forward,18,\n";
+
+    fn diff_str(args: &[&str]) -> Result<String> {
+        let opts = CsvDiffOps::parse_from(["diff", "-", "-"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_diff(&mut OLD.as_bytes(), &mut NEW.as_bytes(), &mut buf, &opts)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_process_csv_diff_table() -> Result<()> {
+        assert_eq!(
+            diff_str(&["--key", "Name"])?,
+            "+---------+----------------+------------+-----+---------+\n\
+             | change  | Name           | column     | old | new     |\n\
+             +---------+----------------+------------+-----+---------+\n\
+             | changed | Paulo Dybala   | Kit Number |  21 | 10      |\n\
+             | changed | Paulo Dybala   | Note       |     | captain |\n\
+             | added   | Moise Kean     |            |     |         |\n\
+             | removed | Blaise Matuidi |            |     |         |\n\
+             +---------+----------------+------------+-----+---------+\n\
+             1 added, 1 removed, 1 changed\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_diff_patch() -> Result<()> {
+        let ret = diff_str(&["--key", "Name", "--format", "json"])?;
+        let patch: Value = serde_json::from_str(&ret)?;
+        assert_eq!(
+            patch[0],
+            json!({
+                "op": "replace",
+                "key": {"Name": "Paulo Dybala"},
+                "fields": {
+                    "Kit Number": {"old": "21", "new": "10"},
+                    "Note": {"old": null, "new": "captain"}
+                }
+            })
+        );
+        assert_eq!(patch[1]["op"], "add");
+        assert_eq!(patch[1]["value"]["Kit Number"], "18");
+        assert_eq!(patch[2]["op"], "remove");
+        assert_eq!(patch[2]["key"], json!({"Name": "Blaise Matuidi"}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_diff_errors() {
+        assert!(diff_str(&["--key", "Club"]).is_err());
+        assert!(diff_str(&["--key", "Note"]).is_err());
+    }
+}
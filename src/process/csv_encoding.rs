@@ -0,0 +1,220 @@
+use std::io::{self, Cursor, Read, Write};
+
+use anyhow::{bail, Result};
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use crate::cli::{InputEncoding, OutputFormat};
+use crate::FinishWrite;
+
+// 自动检测编码时读取的字节数
+const SNIFF_BYTES: usize = 64 * 1024;
+
+/// Transcode the input to UTF-8.
+///
+/// A BOM always wins and is stripped. Without one, `auto` guesses the encoding from
+/// the first 64 KiB, and UTF-8 input is passed through untouched.
+pub fn decode_reader<'a>(
+    mut reader: impl Read + 'a,
+    encoding: InputEncoding,
+) -> Result<Box<dyn Read + 'a>> {
+    let (encoding, reader): (_, Box<dyn Read + 'a>) = match encoding {
+        InputEncoding::Label(encoding) => (encoding, Box::new(reader)),
+        InputEncoding::Auto => {
+            let mut head = Vec::with_capacity(SNIFF_BYTES);
+            (&mut reader)
+                .take(SNIFF_BYTES as u64)
+                .read_to_end(&mut head)?;
+            let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+            detector.feed(&head, head.len() < SNIFF_BYTES);
+            let encoding = detector.guess(None, Utf8Detection::Allow);
+            (encoding, Box::new(Cursor::new(head).chain(reader)))
+        }
+    };
+    // 不指定编码时只检查 BOM, 没有 BOM 就原样透传 UTF-8
+    let reader = DecodeReaderBytesBuilder::new()
+        .encoding((encoding != UTF_8).then_some(encoding))
+        .strip_bom(true)
+        .build(reader);
+    Ok(Box::new(reader))
+}
+
+/// Encode UTF-8 output in another encoding, UTF-16 output starts with a BOM.
+pub fn encode_writer<'a>(
+    writer: impl FinishWrite + 'a,
+    encoding: &'static Encoding,
+) -> Box<dyn FinishWrite + 'a> {
+    if encoding == UTF_8 {
+        return Box::new(writer);
+    }
+    Box::new(EncodeWriter {
+        writer,
+        encoding,
+        pending: Vec::new(),
+        started: false,
+    })
+}
+
+/// Binary formats are written byte for byte, they cannot be encoded as text.
+pub(super) fn check_output_encoding(
+    format: OutputFormat,
+    encoding: &'static Encoding,
+) -> Result<()> {
+    if format.is_binary() && encoding != UTF_8 {
+        bail!(
+            "--format {} is binary and cannot be written as {}",
+            format,
+            encoding.name()
+        );
+    }
+    Ok(())
+}
+
+struct EncodeWriter<W> {
+    writer: W,
+    encoding: &'static Encoding,
+    // 上一次写入末尾不完整的 UTF-8 字符
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("checked above");
+
+        // encoding_rs 不支持编码成 UTF-16, 需要自己处理
+        let bytes: Vec<u8> = if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let le = self.encoding == UTF_16LE;
+            let bom = (!self.started).then_some('\u{feff}');
+            bom.into_iter()
+                .chain(text.chars())
+                .collect::<String>()
+                .encode_utf16()
+                .flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() })
+                .collect()
+        } else {
+            let (bytes, _, had_errors) = self.encoding.encode(text);
+            if had_errors {
+                let msg = format!("output cannot be encoded as {}", self.encoding.name());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            bytes.into_owned()
+        };
+        self.writer.write_all(&bytes)?;
+        self.started = true;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_pending()?;
+        self.writer.flush()
+    }
+}
+
+impl<W> EncodeWriter<W> {
+    // 到 flush 时还剩下的字节不会再凑成完整的字符
+    fn check_pending(&self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let msg = format!(
+            "output ends with {} byte(s) of an incomplete UTF-8 character",
+            self.pending.len()
+        );
+        Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+}
+
+impl<W: FinishWrite> FinishWrite for EncodeWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.check_pending()?;
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, SHIFT_JIS};
+
+    fn decode(bytes: &[u8], encoding: InputEncoding) -> Result<String> {
+        let mut ret = String::new();
+        decode_reader(bytes, encoding)?.read_to_string(&mut ret)?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "姓名,位置\n布冯,门将\n";
+        let (gbk, _, _) = GBK.encode(text);
+        assert_eq!(decode(&gbk, InputEncoding::Label(GBK))?, text);
+        assert_eq!(decode(&gbk, InputEncoding::Auto)?, text);
+
+        let text = "名前,背番号\nブッフォン,77\n";
+        let (sjis, _, _) = SHIFT_JIS.encode(text);
+        assert_eq!(decode(&sjis, InputEncoding::Auto)?, text);
+
+        // the BOM wins over the given encoding
+        let utf16: Vec<u8> = "\u{feff}Name\nBuffon\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            decode(&utf16, InputEncoding::Label(UTF_8))?,
+            "Name\nBuffon\n"
+        );
+        assert_eq!(
+            decode(b"\xef\xbb\xbfName\n", InputEncoding::Auto)?,
+            "Name\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_writer() -> Result<()> {
+        let encode = |text: &str, encoding| -> Result<Vec<u8>> {
+            let mut buf = Vec::new();
+            let mut writer = encode_writer(&mut buf, encoding);
+            // 逐字节写入, 多字节字符会被拆开
+            for b in text.as_bytes() {
+                writer.write_all(&[*b])?;
+            }
+            writer.finish()?;
+            drop(writer);
+            Ok(buf)
+        };
+        assert_eq!(
+            encode("布冯,77\n", GBK)?,
+            GBK.encode("布冯,77\n").0.as_ref()
+        );
+        assert_eq!(encode("Né", UTF_16BE)?, b"\xfe\xff\x00N\x00\xe9");
+        assert!(encode("布冯", encoding_rs::WINDOWS_1252).is_err());
+
+        let mut buf = Vec::new();
+        let mut writer = encode_writer(&mut buf, GBK);
+        writer.write_all("布".as_bytes().split_last().unwrap().1)?;
+        let err = writer.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_output_encoding() {
+        assert!(check_output_encoding(OutputFormat::Csv, GBK).is_ok());
+        assert!(check_output_encoding(OutputFormat::Parquet, UTF_8).is_ok());
+        assert_eq!(
+            check_output_encoding(OutputFormat::Msgpack, UTF_16LE)
+                .unwrap_err()
+                .to_string(),
+            "--format msgpack is binary and cannot be written as UTF-16LE"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, bail, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+
+use super::csv_convert::csv_writer;
+use crate::cli::CsvReadOps;
+use crate::{get_uncompressed_reader, sniff};
+
+// xlsx / ods 是 zip 包, xls 是 OLE 复合文档
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const OLE_MAGIC: &[u8] = &[0xd0, 0xcf, 0x11, 0xe0];
+
+/// Open the input of a csv subcommand.
+///
+/// A workbook is recognized by its magic bytes and its `--sheet` is read as CSV in the
+/// dialect of `ops`, so every subcommand can take one. Anything else is read like
+/// `get_uncompressed_reader` does.
+pub fn csv_input_reader(input: &str, ops: &CsvReadOps) -> Result<Box<dyn Read>> {
+    let (magic, mut reader) = sniff(get_uncompressed_reader(input)?, ZIP_MAGIC.len())?;
+    if !magic.starts_with(ZIP_MAGIC) && !magic.starts_with(OLE_MAGIC) {
+        if ops.sheet.is_some() {
+            bail!("--sheet only applies to Excel or OpenDocument input");
+        }
+        return Ok(Box::new(reader));
+    }
+
+    // 读取工作簿需要随机访问, 整个读进内存
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    let csv = sheet_to_csv(content, ops)?;
+    Ok(Box::new(Cursor::new(csv)))
+}
+
+fn sheet_to_csv(content: Vec<u8>, ops: &CsvReadOps) -> Result<Vec<u8>> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(content))?;
+    let names = workbook.sheet_names();
+    let name = match &ops.sheet {
+        Some(sheet) if names.contains(sheet) => sheet.clone(),
+        Some(sheet) => bail!(
+            "Sheet `{}` not found in the workbook, it has: {}",
+            sheet,
+            names.join(", ")
+        ),
+        None => names
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("The workbook has no sheets"))?,
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    // 以 UTF-8 BOM 开头, 不管 --encoding 是什么都按 UTF-8 读
+    let mut buf = b"\xef\xbb\xbf".to_vec();
+    let mut writer = csv_writer(&mut buf, ops);
+    for row in range.rows() {
+        writer.write_record(row.iter().map(cell_text))?;
+    }
+    writer.flush()?;
+    drop(writer);
+    Ok(buf)
+}
+
+/// Text of a cell as it would appear in a CSV export, dates in ISO-8601.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(dt) if dt.is_datetime() => match dt.as_datetime() {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            None => dt.to_string(),
+        },
+        cell => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_csv, CsvOps};
+    use clap::Parser;
+    use serde_json::{json, Value};
+
+    fn read(args: &[&str]) -> Result<String> {
+        let opts = CsvOps::parse_from(["csv", "-i", "assets/juventus.xlsx"].iter().chain(args));
+        let mut ret = String::new();
+        csv_input_reader(&opts.input, &opts.read)?.read_to_string(&mut ret)?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_csv_input_reader_xlsx() -> Result<()> {
+        let ret = read(&[])?;
+        let mut lines = ret.trim_start_matches('\u{feff}').lines();
+        assert_eq!(lines.next(), Some("Name,Position,Kit Number,Born,Height"));
+        assert_eq!(
+            lines.next(),
+            Some("Wojciech Szczesny,Goalkeeper,1,1990-04-18,1.95")
+        );
+        assert_eq!(
+            lines.last(),
+            Some("\"Sami Khedira, Jr.\",Central Midfield,6,1987-07-18,")
+        );
+
+        let ret = read(&["--sheet", "Staff", "-d", ";"])?;
+        assert!(ret.ends_with("Name;Role\nMaurizio Sarri;Manager\n"));
+
+        let err = read(&["--sheet", "Clubs"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sheet `Clubs` not found in the workbook, it has: Players, Staff"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_xlsx() -> Result<()> {
+        let opts = CsvOps::parse_from(["csv", "-i", "assets/juventus.xlsx", "--infer"]);
+        let mut reader = csv_input_reader(&opts.input, &opts.read)?;
+        let mut buf = Vec::new();
+        process_csv(&mut reader, &mut buf, &opts)?;
+        let ret: Value = serde_json::from_slice(&buf)?;
+        assert_eq!(
+            ret[2],
+            json!({
+                "Name": "Paulo Dybala",
+                "Position": "Second Striker",
+                "Kit Number": 10,
+                "Born": "1993-07-09",
+                "Height": 1.77
+            })
+        );
+
+        let opts = CsvOps::parse_from(["csv", "-i", "assets/juventus.csv", "--sheet", "Staff"]);
+        assert!(csv_input_reader(&opts.input, &opts.read).is_err());
+        Ok(())
+    }
+}
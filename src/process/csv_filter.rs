@@ -0,0 +1,350 @@
+use std::{cmp::Ordering, collections::VecDeque};
+
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+
+/// A parsed `--where` expression, e.g. `Position == "Goalkeeper" && Kit Number > 10`.
+///
+/// Column names are bare words (spaces allowed) or `` `quoted` ``, literals are
+/// numbers or quoted strings. Values compare as numbers when both sides are numeric.
+/// An empty cell or a non-finite number (`NaN`, `inf`) is like SQL's NULL: only
+/// `!=` matches it, and `== ""` matches empty cells.
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+}
+
+#[derive(Debug)]
+enum Operand {
+    Column(usize),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Words(Vec<String>),
+    Quoted(String),
+    Column(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl Filter {
+    pub fn parse(expr: &str, headers: &StringRecord) -> Result<Self> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser { tokens, headers };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.tokens.front() {
+            bail!("Unexpected {:?} in --where expression", token);
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, record: &StringRecord) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+impl Expr {
+    fn eval(&self, record: &StringRecord) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(record) && b.eval(record),
+            Expr::Or(a, b) => a.eval(record) || b.eval(record),
+            Expr::Not(e) => !e.eval(record),
+            Expr::Compare(l, op, r) => {
+                let (l, r) = (l.value(record), r.value(record));
+                let ord = match (l, r) {
+                    ("", "") => Some(Ordering::Equal),
+                    // 空单元格没有大小, 不和任何值比较
+                    ("", _) | (_, "") => None,
+                    _ => match (l.parse::<f64>(), r.parse::<f64>()) {
+                        (Ok(a), Ok(b)) if a.is_finite() && b.is_finite() => a.partial_cmp(&b),
+                        // NaN / inf 既不按数字也不按字符串比较
+                        (Ok(n), _) | (_, Ok(n)) if !n.is_finite() => None,
+                        _ => Some(l.cmp(r)),
+                    },
+                };
+                match (op, ord) {
+                    (Op::Eq, Some(o)) => o == Ordering::Equal,
+                    (Op::Ne, o) => o != Some(Ordering::Equal),
+                    (Op::Lt, Some(o)) => o == Ordering::Less,
+                    (Op::Le, Some(o)) => o != Ordering::Greater,
+                    (Op::Gt, Some(o)) => o == Ordering::Greater,
+                    (Op::Ge, Some(o)) => o != Ordering::Less,
+                    (_, None) => false,
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, record: &'a StringRecord) -> &'a str {
+        match self {
+            Operand::Column(idx) => record.get(*idx).unwrap_or_default(),
+            Operand::Literal(s) => s,
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: VecDeque<Token>,
+    headers: &'a StringRecord,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.pop_front()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.front()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => bail!("Missing `)` in --where expression"),
+                }
+            }
+            _ => {
+                let left = self.parse_operand()?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => bail!("Expected a comparison operator in --where expression"),
+                };
+                let right = self.parse_operand()?;
+                Ok(Expr::Compare(left, op, right))
+            }
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token::Quoted(s)) => Ok(Operand::Literal(s)),
+            Some(Token::Column(name)) => self.column(&name),
+            Some(Token::Words(words)) => {
+                let name = words.join(" ");
+                // 单个数字且不是列名时当作数字字面量
+                if words.len() == 1
+                    && name.parse::<f64>().is_ok()
+                    && !self.headers.iter().any(|h| h == name)
+                {
+                    return Ok(Operand::Literal(name));
+                }
+                self.column(&name)
+            }
+            _ => bail!("Expected a column or a value in --where expression"),
+        }
+    }
+
+    fn column(&self, name: &str) -> Result<Operand> {
+        self.headers
+            .iter()
+            .position(|h| h == name)
+            .map(Operand::Column)
+            .ok_or_else(|| anyhow!("Column `{}` in --where not found in the input", name))
+    }
+}
+
+fn tokenize(expr: &str) -> Result<VecDeque<Token>> {
+    let mut tokens = VecDeque::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '"' | '\'' | '`' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => s.extend(chars.next()),
+                        Some(ch) if ch == c => break,
+                        Some(ch) => s.push(ch),
+                        None => bail!("Unterminated {} in --where expression", c),
+                    }
+                }
+                if c == '`' {
+                    Token::Column(s)
+                } else {
+                    Token::Quoted(s)
+                }
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                }
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                match (c, eq) {
+                    ('=', _) => Token::Op(Op::Eq),
+                    ('!', true) => Token::Op(Op::Ne),
+                    ('!', false) => Token::Not,
+                    ('<', true) => Token::Op(Op::Le),
+                    ('<', false) => Token::Op(Op::Lt),
+                    ('>', true) => Token::Op(Op::Ge),
+                    ('>', false) => Token::Op(Op::Gt),
+                    ('&', false) if chars.next_if_eq(&'&').is_some() => Token::And,
+                    ('|', false) if chars.next_if_eq(&'|').is_some() => Token::Or,
+                    _ => bail!("Unknown operator `{}` in --where expression", c),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(ch) = chars.next_if(|ch| !is_special(*ch)) {
+                    word.push(ch);
+                }
+                // 相邻的单词组成一个列名, 例如 Kit Number
+                if let Some(Token::Words(words)) = tokens.back_mut() {
+                    words.push(word);
+                    continue;
+                }
+                Token::Words(vec![word])
+            }
+        };
+        tokens.push_back(token);
+    }
+    Ok(tokens)
+}
+
+fn is_special(c: char) -> bool {
+    c.is_whitespace() || "\"'`()=!<>&|".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> StringRecord {
+        StringRecord::from(vec!["Name", "Position", "Nationality", "Kit Number"])
+    }
+
+    fn filter(expr: &str) -> Result<Vec<&'static str>> {
+        let players = [
+            ["Wojciech Szczesny", "Goalkeeper", "Poland", "1"],
+            ["Mattia Perin", "Goalkeeper", "Italy", "37"],
+            ["Leonardo Bonucci", "Centre-Back", "Italy", "19"],
+            ["Paulo Dybala", "Second Striker", "Argentina", "10"],
+        ];
+        let filter = Filter::parse(expr, &headers())?;
+        Ok(players
+            .iter()
+            .filter(|p| filter.matches(&StringRecord::from(p.to_vec())))
+            .map(|p| p[0])
+            .collect())
+    }
+
+    #[test]
+    fn test_filter_matches() -> Result<()> {
+        assert_eq!(
+            filter(r#"Position == "Goalkeeper" && Kit Number > 10"#)?,
+            ["Mattia Perin"]
+        );
+        assert_eq!(
+            filter("Nationality == 'Italy' || `Kit Number` <= 1")?,
+            ["Wojciech Szczesny", "Mattia Perin", "Leonardo Bonucci"]
+        );
+        assert_eq!(
+            filter(r#"!(Nationality == "Italy") && Kit Number != 1"#)?,
+            ["Paulo Dybala"]
+        );
+        // 9 < 10 numerically, but "9" > "10" as strings
+        assert_eq!(filter("Kit Number >= 9 && Kit Number < 20")?.len(), 2);
+        assert_eq!(filter(r#"Name < "M""#)?, ["Leonardo Bonucci"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_empty_and_non_finite() -> Result<()> {
+        let players = [
+            ["Gianluigi Buffon", "Goalkeeper", "Italy", ""],
+            ["Mattia Perin", "Goalkeeper", "Italy", "NaN"],
+            ["Carlo Pinsoglio", "Goalkeeper", "Italy", "inf"],
+            ["Wojciech Szczesny", "Goalkeeper", "Poland", "1"],
+        ];
+        let filter = |expr: &str| -> Result<Vec<&str>> {
+            let filter = Filter::parse(expr, &headers())?;
+            Ok(players
+                .iter()
+                .filter(|p| filter.matches(&StringRecord::from(p.to_vec())))
+                .map(|p| p[0])
+                .collect())
+        };
+        assert_eq!(filter("Kit Number < 10")?, ["Wojciech Szczesny"]);
+        assert_eq!(filter("Kit Number >= 1")?, ["Wojciech Szczesny"]);
+        assert_eq!(filter("!(Kit Number > 1)")?.len(), 4);
+        assert_eq!(filter("Kit Number == 'NaN'")?, Vec::<&str>::new());
+        assert_eq!(filter("Kit Number == ''")?, ["Gianluigi Buffon"]);
+        assert_eq!(
+            filter("Kit Number != 1")?,
+            ["Gianluigi Buffon", "Mattia Perin", "Carlo Pinsoglio"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        assert!(filter("Club == \"Juventus\"").is_err());
+        assert!(filter("Position == \"Goalkeeper").is_err());
+        assert!(filter("(Kit Number > 1").is_err());
+        assert!(filter("Kit Number").is_err());
+        assert!(filter("Kit Number > 1 Name").is_err());
+        assert!(filter("Kit Number > 1 & Name == 'x'").is_err());
+    }
+}
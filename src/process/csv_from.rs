@@ -0,0 +1,179 @@
+use std::{collections::HashSet, io::Read, path::Path};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::csv_convert::csv_writer;
+use super::csv_encoding::decode_reader;
+use super::csv_output::cell;
+use crate::cli::{CsvFromOps, InputFormat};
+use crate::FinishWrite;
+
+/// Read an array of objects (or a stream of them) and write it back as CSV.
+///
+/// The header is the union of every key in first-seen order, so the records are
+/// kept in memory until all keys are known.
+pub fn process_csv_from(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvFromOps,
+) -> Result<()> {
+    let format = match opts.format {
+        Some(format) => format,
+        None => guess_format(&opts.input)?,
+    };
+
+    let mut rows = Vec::new();
+    let mut reader = decode_reader(reader, opts.read.encoding)?;
+    for value in read_values(&mut reader, format)? {
+        let mut row = Map::new();
+        flatten_value("", &value, &opts.separator, &mut row);
+        rows.push(row);
+    }
+
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    for key in rows.iter().flat_map(|row| row.keys()) {
+        if seen.insert(key.as_str()) {
+            headers.push(key.clone());
+        }
+    }
+
+    let mut output = csv_writer(&mut *writer, &opts.read);
+    if opts.read.header {
+        output.write_record(&headers)?;
+    }
+    for row in &rows {
+        let fields = headers
+            .iter()
+            .map(|h| row.get(h).map(cell).unwrap_or_default());
+        output.write_record(fields)?;
+    }
+    output.flush()?;
+    drop(output);
+    writer.finish()?;
+    Ok(())
+}
+
+fn guess_format(input: &str) -> Result<InputFormat> {
+    let ext = Path::new(input)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    match ext {
+        "json" => Ok(InputFormat::Json),
+        "yaml" | "yml" => Ok(InputFormat::Yaml),
+        "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+        _ => bail!("Cannot guess the format of `{}`, use --format", input),
+    }
+}
+
+/// Records of the input: the items of a top level array, or the values themselves.
+fn read_values(reader: &mut dyn Read, format: InputFormat) -> Result<Vec<Value>> {
+    let mut ret = Vec::new();
+    let mut push = |value: Value| match value {
+        Value::Array(items) => ret.extend(items),
+        v => ret.push(v),
+    };
+    match format {
+        InputFormat::Json => push(serde_json::from_reader(reader)?),
+        InputFormat::Yaml => {
+            // 一个 yaml 文件可以包含多个 --- 分隔的文档
+            for doc in serde_yaml::Deserializer::from_reader(reader) {
+                push(Value::deserialize(doc)?);
+            }
+        }
+        InputFormat::Ndjson => {
+            for value in serde_json::Deserializer::from_reader(reader).into_iter() {
+                push(value?);
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Flatten nested objects into `a.b` columns and arrays into `a[0]` columns.
+fn flatten_value(prefix: &str, value: &Value, separator: &str, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}{}{}", prefix, separator, k)
+                };
+                flatten_value(&key, v, separator, out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() && !prefix.is_empty() => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_value(&format!("{}[{}]", prefix, i), v, separator, out);
+            }
+        }
+        v => {
+            let key = if prefix.is_empty() { "value" } else { prefix };
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn from_str(input: &str, args: &[&str]) -> Result<String> {
+        let opts = CsvFromOps::parse_from(["from"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_from(&mut input.as_bytes(), &mut buf, &opts)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_process_csv_from_json() -> Result<()> {
+        let input = r#"[
+            {"Name": "Buffon", "Kit Number": 77, "address": {"city": "Turin", "zip": "10151"}},
+            {"Name": "Dybala, Paulo", "tags": ["captain", "10"], "Retired": false}
+        ]"#;
+        let ret = from_str(input, &["--format", "json"])?;
+        assert_eq!(
+            ret,
+            "Name,Kit Number,address.city,address.zip,tags[0],tags[1],Retired\n\
+             Buffon,77,Turin,10151,,,\n\
+             \"Dybala, Paulo\",,,,captain,10,false\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from_yaml_and_ndjson() -> Result<()> {
+        let yaml =
+            "- Name: Buffon\n  Position: Goalkeeper\n---\nName: Chiellini\nPosition: Centre-Back\n";
+        let ndjson = "{\"Name\":\"Buffon\",\"Position\":\"Goalkeeper\"}\n{\"Name\":\"Chiellini\",\"Position\":\"Centre-Back\"}\n";
+        let expected = "Name;Position\nBuffon;Goalkeeper\nChiellini;Centre-Back\n";
+        assert_eq!(from_str(yaml, &["--format", "yaml", "-d", ";"])?, expected);
+        assert_eq!(
+            from_str(ndjson, &["--format", "ndjson", "-d", ";"])?,
+            expected
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from_options() -> Result<()> {
+        let input = r#"[{"a": {"b": 1}}]"#;
+        assert_eq!(
+            from_str(input, &["--format", "json", "--separator", "/"])?,
+            "a/b\n1\n"
+        );
+        assert_eq!(
+            from_str(input, &["--format", "json", "--header", "false"])?,
+            "1\n"
+        );
+        assert!(from_str(input, &[]).is_err());
+        // 只能输出的格式在解析参数时就被拒绝
+        assert!(CsvFromOps::try_parse_from(["from", "--format", "toml"]).is_err());
+        Ok(())
+    }
+}
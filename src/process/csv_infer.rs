@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde_json::{Number, Value};
+
+use crate::cli::CellType;
+
+// 尝试的日期格式, 日期之后的内容(例如年龄 "(29)")原样保留
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%d/%m/%Y",
+    "%b %-d, %Y",
+    "%-d %b %Y",
+];
+
+/// Guess the type of a cell: empty cells become null, then int, float, bool and
+/// ISO-8601 date are tried in that order, anything else stays a string.
+pub fn infer_value(s: &str) -> Value {
+    if s.is_empty() {
+        return Value::Null;
+    }
+    // 前导 0 的数字(邮编, 编号)保留为字符串
+    let digits = s.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return Value::String(s.to_string());
+    }
+
+    if let Some(v) = parse_int(s) {
+        v
+    } else if let Some(v) = parse_float(s) {
+        v
+    } else if let Some(v) = parse_iso_date(s) {
+        v
+    } else {
+        match s {
+            "true" | "TRUE" | "True" => Value::Bool(true),
+            "false" | "FALSE" | "False" => Value::Bool(false),
+            _ => Value::String(s.to_string()),
+        }
+    }
+}
+
+/// Convert a cell to the pinned type, empty cells become null for every type but string.
+pub fn parse_value(s: &str, ty: CellType) -> Result<Value> {
+    if s.is_empty() && ty != CellType::String {
+        return Ok(Value::Null);
+    }
+    let value = match ty {
+        CellType::String => Some(Value::String(s.to_string())),
+        CellType::Int => parse_int(s),
+        CellType::Float => parse_float(s),
+        CellType::Bool => parse_bool(s),
+        CellType::Date => parse_iso_date(s),
+    };
+    value.ok_or_else(|| anyhow!("`{}` is not a valid {}", s, ty))
+}
+
+fn parse_int(s: &str) -> Option<Value> {
+    s.parse::<i64>().ok().map(Value::from)
+}
+
+fn parse_float(s: &str) -> Option<Value> {
+    // f64 也接受 "inf" / "NaN", 这里只要有限的数字
+    let f = s.parse::<f64>().ok()?;
+    Number::from_f64(f)
+        .filter(|_| f.is_finite() && s.bytes().any(|b| b.is_ascii_digit()))
+        .map(Value::Number)
+}
+
+fn parse_bool(s: &str) -> Option<Value> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" => Some(Value::Bool(true)),
+        "false" | "no" | "n" | "0" => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+/// ISO-8601 dates and date-times, normalized so equal instants print the same.
+fn parse_iso_date(s: &str) -> Option<Value> {
+    let normalized = if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        d.format("%Y-%m-%d").to_string()
+    } else if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        dt.to_rfc3339()
+    } else if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+    {
+        dt.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+    } else {
+        return None;
+    };
+    Some(Value::String(normalized))
+}
+
+/// The date at the start of `value`, the format it matched and what follows it.
+pub fn parse_date(value: &str) -> Option<(NaiveDate, &'static str, &str)> {
+    DATE_FORMATS.iter().find_map(|fmt| {
+        NaiveDate::parse_and_remainder(value, fmt)
+            .ok()
+            .map(|(date, rest)| (date, *fmt, rest))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_value() {
+        assert_eq!(infer_value(""), Value::Null);
+        assert_eq!(infer_value("37"), json!(37));
+        assert_eq!(infer_value("-3"), json!(-3));
+        assert_eq!(infer_value("0"), json!(0));
+        assert_eq!(infer_value("0.5"), json!(0.5));
+        assert_eq!(infer_value("1e3"), json!(1000.0));
+        assert_eq!(infer_value("true"), json!(true));
+        assert_eq!(infer_value("1990-04-18"), json!("1990-04-18"));
+        assert_eq!(
+            infer_value("1990-04-18T10:00:00+02:00"),
+            json!("1990-04-18T10:00:00+02:00")
+        );
+        assert_eq!(infer_value("007"), json!("007"));
+        assert_eq!(infer_value("NaN"), json!("NaN"));
+        assert_eq!(infer_value("Apr 18, 1990 (29)"), json!("Apr 18, 1990 (29)"));
+    }
+
+    #[test]
+    fn test_parse_value() -> Result<()> {
+        assert_eq!(parse_value("007", CellType::Int)?, json!(7));
+        assert_eq!(parse_value("", CellType::Int)?, Value::Null);
+        assert_eq!(parse_value("", CellType::String)?, json!(""));
+        assert_eq!(parse_value("12", CellType::Float)?, json!(12.0));
+        assert_eq!(parse_value("yes", CellType::Bool)?, json!(true));
+        assert_eq!(parse_value("37", CellType::String)?, json!("37"));
+
+        let err = parse_value("1990-02-30", CellType::Date).unwrap_err();
+        assert_eq!(err.to_string(), "`1990-02-30` is not a valid date");
+        assert!(parse_value("Goalkeeper", CellType::Int).is_err());
+        Ok(())
+    }
+}
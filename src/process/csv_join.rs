@@ -0,0 +1,417 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Read, Write},
+};
+
+use anyhow::{anyhow, Result};
+use csv::{Reader, StringRecord};
+use encoding_rs::UTF_8;
+use serde_json::{Map, Value};
+use tempfile::TempDir;
+
+use super::csv_convert::{csv_headers, csv_reader, csv_writer};
+use super::csv_encoding::check_output_encoding;
+use super::csv_output::{record_writer, RecordWriter};
+use super::csv_sort::{process_csv_sort, sort_key, SortKey};
+use crate::cli::{CsvJoinOps, CsvReadOps, CsvSortOps, InputEncoding, JoinHow};
+use crate::FinishWrite;
+
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    // 键列只输出一次, 取有值的那一边
+    Key(usize, usize),
+    Left(usize),
+    Right(usize),
+}
+
+/// Output columns of the join: the keys, then the other left and right columns.
+#[derive(Debug)]
+struct Layout {
+    columns: Vec<(String, Source)>,
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+}
+
+impl Layout {
+    fn new(left: &StringRecord, right: &StringRecord, opts: &CsvJoinOps) -> Result<Self> {
+        let index = |headers: &StringRecord, name: &str, side: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("Column `{}` not found in the {} input", name, side))
+        };
+        let mut columns = Vec::new();
+        let mut left_keys = Vec::new();
+        let mut right_keys = Vec::new();
+        for name in &opts.on {
+            let (l, r) = (index(left, name, "left")?, index(right, name, "right")?);
+            columns.push((name.clone(), Source::Key(l, r)));
+            left_keys.push(l);
+            right_keys.push(r);
+        }
+
+        // 两边都有的非键列加上前缀区分
+        let is_key = |name: &str| opts.on.iter().any(|k| k == name);
+        let contains = |headers: &StringRecord, name: &str| headers.iter().any(|h| h == name);
+        for (i, name) in left.iter().enumerate().filter(|(_, h)| !is_key(h)) {
+            let name = if contains(right, name) {
+                format!("{}{}", opts.left_prefix, name)
+            } else {
+                name.to_string()
+            };
+            columns.push((name, Source::Left(i)));
+        }
+        for (i, name) in right.iter().enumerate().filter(|(_, h)| !is_key(h)) {
+            let name = if contains(left, name) {
+                format!("{}{}", opts.right_prefix, name)
+            } else {
+                name.to_string()
+            };
+            columns.push((name, Source::Right(i)));
+        }
+
+        Ok(Self {
+            columns,
+            left_keys,
+            right_keys,
+        })
+    }
+
+    fn row(&self, left: Option<&StringRecord>, right: Option<&StringRecord>) -> Value {
+        let mut row = Map::new();
+        for (name, source) in &self.columns {
+            let value = match *source {
+                Source::Key(l, r) => get(left, l).or_else(|| get(right, r)),
+                Source::Left(i) => get(left, i),
+                Source::Right(i) => get(right, i),
+            };
+            let value = value.map_or(Value::Null, |v| Value::String(v.to_string()));
+            row.insert(name.clone(), value);
+        }
+        Value::Object(row)
+    }
+}
+
+fn get(record: Option<&StringRecord>, i: usize) -> Option<&str> {
+    record.and_then(|r| r.get(i))
+}
+
+fn key<'a>(record: &'a StringRecord, columns: &[usize]) -> Vec<&'a str> {
+    columns
+        .iter()
+        .map(|&i| record.get(i).unwrap_or_default())
+        .collect()
+}
+
+struct Joiner<'a> {
+    layout: Layout,
+    how: JoinHow,
+    output: Box<dyn RecordWriter + 'a>,
+}
+
+impl Joiner<'_> {
+    fn emit(&mut self, left: Option<&StringRecord>, right: Option<&StringRecord>) -> Result<()> {
+        self.output.write_record(&self.layout.row(left, right))
+    }
+
+    /// Hash join: the right rows are indexed in memory and the left input is streamed.
+    fn hash_join<R: Read>(&mut self, left: &mut Reader<R>, right: &[StringRecord]) -> Result<()> {
+        let mut index: HashMap<Vec<&str>, Vec<usize>> = HashMap::new();
+        for (i, record) in right.iter().enumerate() {
+            index
+                .entry(key(record, &self.layout.right_keys))
+                .or_default()
+                .push(i);
+        }
+
+        let mut matched = vec![false; right.len()];
+        let mut record = StringRecord::new();
+        while left.read_record(&mut record)? {
+            match index.get(&key(&record, &self.layout.left_keys)) {
+                Some(rows) => {
+                    for &i in rows {
+                        self.emit(Some(&record), Some(&right[i]))?;
+                        matched[i] = true;
+                    }
+                }
+                None if self.how != JoinHow::Inner => self.emit(Some(&record), None)?,
+                None => {}
+            }
+        }
+        if self.how == JoinHow::Outer {
+            for (record, _) in right.iter().zip(matched).filter(|(_, m)| !m) {
+                self.emit(None, Some(record))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sort-merge join of two inputs sorted by the key columns.
+    fn merge_join(&mut self, left: &mut Groups, right: &mut Groups) -> Result<()> {
+        let mut l = left.next_group()?;
+        let mut r = right.next_group()?;
+        loop {
+            let ord = match (&l, &r) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((lkey, _)), Some((rkey, _))) => lkey.cmp(rkey),
+            };
+            match ord {
+                Ordering::Less => {
+                    if let Some((_, rows)) = l {
+                        self.join_groups(&rows, &[])?;
+                    }
+                    l = left.next_group()?;
+                }
+                Ordering::Greater => {
+                    if let Some((_, rows)) = r {
+                        self.join_groups(&[], &rows)?;
+                    }
+                    r = right.next_group()?;
+                }
+                Ordering::Equal => {
+                    if let (Some((_, lrows)), Some((_, rrows))) = (&l, &r) {
+                        self.join_groups(lrows, rrows)?;
+                    }
+                    l = left.next_group()?;
+                    r = right.next_group()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rows of both sides whose typed keys are equal, matched on the exact key text.
+    fn join_groups(&mut self, left: &[StringRecord], right: &[StringRecord]) -> Result<()> {
+        let mut matched = vec![false; right.len()];
+        for l in left {
+            let lkey = key(l, &self.layout.left_keys);
+            let mut found = false;
+            for (i, r) in right.iter().enumerate() {
+                if lkey == key(r, &self.layout.right_keys) {
+                    self.emit(Some(l), Some(r))?;
+                    matched[i] = true;
+                    found = true;
+                }
+            }
+            if !found && self.how != JoinHow::Inner {
+                self.emit(Some(l), None)?;
+            }
+        }
+        if self.how == JoinHow::Outer {
+            for (r, _) in right.iter().zip(matched).filter(|(_, m)| !m) {
+                self.emit(None, Some(r))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a sorted input one group of rows with the same typed key at a time.
+struct Groups {
+    reader: Reader<Box<dyn Read>>,
+    keys: Vec<usize>,
+    pending: Option<StringRecord>,
+}
+
+impl Groups {
+    fn new(mut reader: Reader<Box<dyn Read>>, keys: Vec<usize>) -> Result<Self> {
+        let mut record = StringRecord::new();
+        let pending = reader.read_record(&mut record)?.then_some(record);
+        Ok(Self {
+            reader,
+            keys,
+            pending,
+        })
+    }
+
+    fn next_group(&mut self) -> Result<Option<(Vec<SortKey>, Vec<StringRecord>)>> {
+        let Some(first) = self.pending.take() else {
+            return Ok(None);
+        };
+        let key = sort_key(&first, &self.keys);
+        let mut rows = vec![first];
+        let mut record = StringRecord::new();
+        while self.reader.read_record(&mut record)? {
+            if sort_key(&record, &self.keys) != key {
+                self.pending = Some(record);
+                break;
+            }
+            rows.push(record.clone());
+        }
+        Ok(Some((key, rows)))
+    }
+}
+
+/// Join two CSV inputs on the `--on` columns.
+///
+/// When the right input has at most `--buffer-rows` rows it is held in memory and the
+/// left input is streamed through a hash join, keeping the order of the left input.
+/// Otherwise both inputs are sorted on disk with `csv sort` and merged, and the rows
+/// come out in key order.
+pub fn process_csv_join(
+    left: &mut dyn Read,
+    right: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvJoinOps,
+) -> Result<()> {
+    check_output_encoding(opts.format, opts.read.output_encoding)?;
+    let mut right = csv_reader(right, &opts.read)?;
+    let right_headers = csv_headers(&mut right)?;
+    let mut rows = Vec::new();
+    let mut record = StringRecord::new();
+    let mut fits = true;
+    while right.read_record(&mut record)? {
+        if rows.len() >= opts.buffer_rows {
+            fits = false;
+            break;
+        }
+        rows.push(record.clone());
+    }
+
+    let output = record_writer(writer, opts.format);
+    if fits {
+        let mut left = csv_reader(left, &opts.read)?;
+        let left_headers = csv_headers(&mut left)?;
+        let layout = Layout::new(&left_headers, &right_headers, opts)?;
+        let mut joiner = Joiner {
+            layout,
+            how: opts.how,
+            output,
+        };
+        joiner.hash_join(&mut left, &rows)?;
+        return joiner.output.finish();
+    }
+
+    // 右边放不下: 先把读过的行和剩下的行写到临时文件, 两边都排好序后合并
+    let dir = match &opts.temp_dir {
+        Some(dir) => TempDir::new_in(dir)?,
+        None => TempDir::new()?,
+    };
+    let right_path = dir.path().join("right.csv");
+    let mut writer = csv_writer(File::create(&right_path)?, &opts.read);
+    if opts.read.header {
+        writer.write_record(&right_headers)?;
+    }
+    for row in rows.iter().chain([&record]) {
+        writer.write_record(row)?;
+    }
+    while right.read_record(&mut record)? {
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    // 临时文件都是 UTF-8 编码
+    let utf8 = CsvReadOps {
+        encoding: InputEncoding::Label(UTF_8),
+        ..opts.read.clone()
+    };
+    let sorted = |reader: &mut dyn Read, read: &CsvReadOps, name: &str| -> Result<_> {
+        let sort_opts = CsvSortOps {
+            input: "-".to_string(),
+            output: None,
+            by: opts.on.clone(),
+            desc: false,
+            dedup_by: Vec::new(),
+            buffer_rows: opts.buffer_rows,
+            temp_dir: opts.temp_dir.clone(),
+            read: read.clone(),
+        };
+        let path = dir.path().join(name);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        process_csv_sort(reader, &mut writer, &sort_opts)?;
+        writer.flush()?;
+        csv_reader(File::open(&path)?, &utf8)
+    };
+    let mut left = sorted(left, &opts.read, "left.sorted.csv")?;
+    let mut right = sorted(&mut File::open(&right_path)?, &utf8, "right.sorted.csv")?;
+    let left_headers = csv_headers(&mut left)?;
+    let right_headers = csv_headers(&mut right)?;
+    let layout = Layout::new(&left_headers, &right_headers, opts)?;
+    let mut left = Groups::new(left, layout.left_keys.clone())?;
+    let mut right = Groups::new(right, layout.right_keys.clone())?;
+    let mut joiner = Joiner {
+        layout,
+        how: opts.how,
+        output,
+    };
+    joiner.merge_join(&mut left, &mut right)?;
+    joiner.output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const ROSTER: &str = "Name,Position,Note\n\
+                          Paulo Dybala,Second Striker,captain\n\
+                          Mattia Perin,Goalkeeper,\n\
+                          Moise Kean,Centre-Forward,loan\n";
+    const CONTRACTS: &str = "Name,Until,Note\n\
+                             Mattia Perin,2022,\n\
+                             Paulo Dybala,2022,renewal\n\
+                             Blaise Matuidi,2020,\n";
+
+    fn join(args: &[&str]) -> Result<Vec<String>> {
+        let opts = CsvJoinOps::parse_from(["join", "-", "-"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_join(
+            &mut ROSTER.as_bytes(),
+            &mut CONTRACTS.as_bytes(),
+            &mut buf,
+            &opts,
+        )?;
+        Ok(String::from_utf8(buf)?.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_process_csv_join_hash() -> Result<()> {
+        assert_eq!(
+            join(&["--on", "Name"])?,
+            [
+                "Name,Position,left.Note,Until,right.Note",
+                "Paulo Dybala,Second Striker,captain,2022,renewal",
+                "Mattia Perin,Goalkeeper,,2022,",
+            ]
+        );
+        let ret = join(&["--on", "Name", "--how", "left"])?;
+        assert_eq!(ret.len(), 4);
+        assert_eq!(ret[3], "Moise Kean,Centre-Forward,loan,,");
+        let ret = join(&[
+            "--on",
+            "Name",
+            "--how",
+            "outer",
+            "--right-prefix",
+            "contract_",
+        ])?;
+        assert_eq!(ret[0], "Name,Position,left.Note,Until,contract_Note");
+        assert_eq!(ret[4], "Blaise Matuidi,,,2020,");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_join_merge() -> Result<()> {
+        for how in ["inner", "left", "outer"] {
+            let mut hash = join(&["--on", "Name", "--how", how])?;
+            let mut merge = join(&["--on", "Name", "--how", how, "--buffer-rows", "1"])?;
+            // the merge join writes rows in key order
+            assert!(merge[1..].windows(2).all(|w| w[0] <= w[1]));
+            hash.sort();
+            merge.sort();
+            assert_eq!(merge, hash);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_join_errors() {
+        assert!(join(&["--on", "Club"]).is_err());
+        assert!(join(&["--on", "Position"]).is_err());
+    }
+}
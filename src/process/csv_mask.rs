@@ -0,0 +1,272 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Duration;
+use csv::StringRecord;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::csv_convert::{csv_reader, csv_writer};
+use super::csv_infer::parse_date;
+use super::text::{Blake3, TextSigner};
+use crate::cli::CsvMaskOps;
+use crate::FinishWrite;
+
+// 伪名取 keyed hash 的前 8 个字节
+const PSEUDONYM_BYTES: usize = 8;
+// 未指定 --shift-days 时, 由 key 推导出 ±365 天内的偏移
+const MAX_SHIFT_DAYS: i64 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mask {
+    Hash,
+    Redact,
+    Fake,
+    ShiftDate,
+}
+
+struct Masker {
+    hasher: Option<Blake3>,
+    redact_with: String,
+    shift_days: i64,
+}
+
+impl Masker {
+    fn keyed_hash(&self, domain: &str, value: &str) -> Result<Vec<u8>> {
+        let hasher = self
+            .hasher
+            .as_ref()
+            .ok_or_else(|| anyhow!("--key is required for --hash, --fake and --shift-date"))?;
+        let data = format!("{}:{}", domain, value);
+        hasher.sign(&mut data.as_bytes())
+    }
+
+    fn mask(&self, mask: Mask, value: &str) -> Result<String> {
+        if value.is_empty() {
+            return Ok(String::new());
+        }
+        match mask {
+            Mask::Hash => {
+                // 同一个 key 下相同的值总是得到相同的伪名, 跨文件也可以关联
+                let hash = self.keyed_hash("hash", value)?;
+                Ok(hash[..PSEUDONYM_BYTES]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect())
+            }
+            Mask::Redact => Ok(self.redact_with.clone()),
+            Mask::Fake => {
+                let seed = self.keyed_hash("fake", value)?;
+                let mut rng = StdRng::from_seed(seed.try_into().expect("blake3 hash is 32 bytes"));
+                Ok(value.chars().map(|c| fake_char(c, &mut rng)).collect())
+            }
+            Mask::ShiftDate => shift_date(value, self.shift_days),
+        }
+    }
+}
+
+/// Keep the shape of the value: letters stay letters of the same case, digits stay digits.
+fn fake_char(c: char, rng: &mut StdRng) -> char {
+    if c.is_ascii_digit() {
+        rng.gen_range(b'0'..=b'9') as char
+    } else if c.is_uppercase() {
+        rng.gen_range(b'A'..=b'Z') as char
+    } else if c.is_alphabetic() {
+        rng.gen_range(b'a'..=b'z') as char
+    } else {
+        c
+    }
+}
+
+fn shift_date(value: &str, days: i64) -> Result<String> {
+    match parse_date(value) {
+        Some((date, fmt, rest)) => {
+            let shifted = date + Duration::days(days);
+            Ok(format!("{}{}", shifted.format(fmt), rest))
+        }
+        None => bail!("`{}` is not a recognized date", value),
+    }
+}
+
+pub fn process_csv_mask(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    key: Option<&[u8]>,
+    opts: &CsvMaskOps,
+) -> Result<()> {
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let mut output = csv_writer(&mut *writer, &opts.read);
+
+    let headers = reader.headers()?.clone();
+    let mut masks = vec![None; headers.len()];
+    let columns = [
+        (&opts.hash, Mask::Hash),
+        (&opts.redact, Mask::Redact),
+        (&opts.fake, Mask::Fake),
+        (&opts.shift_date, Mask::ShiftDate),
+    ];
+    for (names, mask) in columns {
+        for name in names {
+            let idx = column_index(&headers, name, opts.read.header)?;
+            if masks[idx].replace(mask).is_some() {
+                bail!("Column `{}` is masked more than once", name);
+            }
+        }
+    }
+
+    let hasher = key.map(Blake3::try_new).transpose()?;
+    let shift_days = match (opts.shift_days, &hasher) {
+        (Some(days), _) => days,
+        (None, Some(hasher)) => {
+            let hash = hasher.sign(&mut "shift-date".as_bytes())?;
+            let n = i64::from_le_bytes(hash[..8].try_into()?);
+            // 偏移 0 等于没有脱敏, 换成最大偏移
+            match n.rem_euclid(2 * MAX_SHIFT_DAYS) - MAX_SHIFT_DAYS {
+                0 => MAX_SHIFT_DAYS,
+                days => days,
+            }
+        }
+        (None, None) if !opts.shift_date.is_empty() => {
+            bail!("--shift-date needs --shift-days or --key")
+        }
+        (None, None) => 0,
+    };
+    let masker = Masker {
+        hasher,
+        redact_with: opts.redact_with.clone(),
+        shift_days,
+    };
+
+    // 没有表头时 headers() 返回的第一行数据仍会被 read_record 读到
+    if opts.read.header {
+        output.write_record(&headers)?;
+    }
+
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let masked = mask_record(&record, &masks, &masker)?;
+        output.write_record(&masked)?;
+    }
+    output.flush()?;
+    drop(output);
+    writer.finish()?;
+    Ok(())
+}
+
+fn mask_record(
+    record: &StringRecord,
+    masks: &[Option<Mask>],
+    masker: &Masker,
+) -> Result<StringRecord> {
+    record
+        .iter()
+        .zip(masks)
+        .map(|(field, mask)| match mask {
+            Some(mask) => masker.mask(*mask, field).map_err(|e| {
+                let line = record.position().map_or(1, |p| p.line());
+                anyhow!("line {}: {}", line, e)
+            }),
+            None => Ok(field.to_string()),
+        })
+        .collect()
+}
+
+/// Index of a column by name, or by `column_N` when the input has no header row.
+fn column_index(headers: &StringRecord, name: &str, has_header: bool) -> Result<usize> {
+    let idx = if has_header {
+        headers.iter().position(|h| h == name)
+    } else {
+        name.strip_prefix("column_")
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| (1..=headers.len()).contains(n))
+            .map(|n| n - 1)
+    };
+    idx.ok_or_else(|| anyhow!("Column `{}` not found in the input", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    fn mask(input: &str, key: Option<&[u8]>, args: &[&str]) -> Result<Vec<Vec<String>>> {
+        let opts = CsvMaskOps::parse_from(["mask"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_mask(&mut input.as_bytes(), &mut buf, key, &opts)?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(buf.as_slice());
+        let rows = reader
+            .records()
+            .map(|r| Ok(r?.iter().map(String::from).collect()))
+            .collect::<Result<_>>()?;
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_process_csv_mask() -> Result<()> {
+        let input = "Name,DOB,Nationality,Kit Number\n\
+                     Gianluigi Buffon,\"Jan 28, 1978 (41)\",Italy,77\n\
+                     Gianluigi Buffon,1978-01-28,Italy,1\n";
+        let args = [
+            "--hash",
+            "Name",
+            "--redact",
+            "Kit Number",
+            "--fake",
+            "Nationality",
+            "--shift-date",
+            "DOB",
+            "--shift-days",
+            "-30",
+        ];
+        let rows = mask(input, Some(KEY), &args)?;
+        assert_eq!(rows[0], ["Name", "DOB", "Nationality", "Kit Number"]);
+
+        let (first, second) = (&rows[1], &rows[2]);
+        assert_eq!(first[0].len(), 2 * PSEUDONYM_BYTES);
+        assert_ne!(first[0], "Gianluigi Buffon");
+        assert_eq!(first[0], second[0]);
+        assert_eq!(first[1], "Dec 29, 1977 (41)");
+        assert_eq!(second[1], "1977-12-29");
+        assert_ne!(first[2], "Italy");
+        assert!(first[2].starts_with(|c: char| c.is_ascii_uppercase()));
+        assert!(first[2][1..].chars().all(|c| c.is_ascii_lowercase()));
+        assert_eq!(first[2], second[2]);
+        assert_eq!(first[3], "***");
+
+        // pseudonyms are stable across runs with the same key only
+        assert_eq!(mask(input, Some(KEY), &args)?, rows);
+        let other_key = [b'k'; 32];
+        let other = mask(input, Some(&other_key), &args)?;
+        assert_ne!(other[1][0], first[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_mask_errors() {
+        let input = "Name,DOB\nBuffon,unknown\n";
+        assert!(mask(input, None, &["--hash", "Name"]).is_err());
+        assert!(mask(input, Some(KEY), &["--hash", "Club"]).is_err());
+        assert!(mask(input, Some(KEY), &["--hash", "Name", "--redact", "Name"]).is_err());
+        let err = mask(input, Some(KEY), &["--shift-date", "DOB"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: `unknown` is not a recognized date"
+        );
+        let err = mask(input, None, &["--shift-date", "DOB"]).unwrap_err();
+        assert_eq!(err.to_string(), "--shift-date needs --shift-days or --key");
+    }
+
+    #[test]
+    fn test_process_csv_mask_without_header() -> Result<()> {
+        let rows = mask(
+            "Buffon,77\nDybala,10\n",
+            None,
+            &["--header", "false", "--redact", "column_2"],
+        )?;
+        assert_eq!(rows, [["Buffon", "***"], ["Dybala", "***"]]);
+        Ok(())
+    }
+}
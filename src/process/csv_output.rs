@@ -0,0 +1,307 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use csv::WriterBuilder;
+use serde_json::{json, Value};
+
+use super::csv_columnar::ColumnarWriter;
+use crate::cli::OutputFormat;
+use crate::FinishWrite;
+
+/// Writes converted records one at a time, so memory use does not grow with the input.
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &Value) -> Result<()>;
+    /// Close the document (e.g. the JSON array) and finish the underlying writer.
+    fn finish(&mut self) -> Result<()>;
+}
+
+struct JsonWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+struct YamlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+/// One `[[records]]` table per record, TOML has no null so null fields are left out.
+struct TomlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+struct NdjsonWriter<W> {
+    writer: W,
+}
+
+/// A stream of MessagePack maps, one per record.
+struct MsgpackWriter<W> {
+    writer: W,
+}
+
+/// The keys of the first record become the header row.
+struct DelimitedWriter<W: Write> {
+    // finish 时取出, 以便拿回底层 writer 调用 finish
+    writer: Option<csv::Writer<W>>,
+    headers: Option<Vec<String>>,
+}
+
+impl<W: FinishWrite> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let sep = if self.count == 0 { "[\n" } else { ",\n" };
+        self.writer.write_all(sep.as_bytes())?;
+        // same layout as `to_string_pretty` on the whole array
+        let content = serde_json::to_string_pretty(record)?;
+        write_indented(&mut self.writer, &content, "  ", "  ")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        self.writer.write_all(end.as_bytes())?;
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for YamlWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        // each record becomes one item of a top level sequence
+        let content = serde_yaml::to_string(record)?;
+        write_indented(&mut self.writer, content.trim_end(), "- ", "  ")?;
+        self.writer.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for TomlWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        if self.count > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        // 包一层数组, toml 才会输出 [[records]] 以及嵌套的 [records.xxx] 表头
+        let content = toml::to_string(&json!({ "records": [strip_nulls(record)] }))?;
+        self.writer.write_all(content.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for NdjsonWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for MsgpackWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        rmp_serde::encode::write_named(&mut self.writer, record)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+impl<W: FinishWrite> RecordWriter for DelimitedWriter<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Record written after finish"))?;
+        let headers = match &self.headers {
+            Some(headers) => headers,
+            None => {
+                let headers = match record {
+                    Value::Object(map) => map.keys().cloned().collect(),
+                    _ => vec!["value".to_string()],
+                };
+                writer.write_record(&headers)?;
+                self.headers.insert(headers)
+            }
+        };
+
+        match record {
+            Value::Object(map) => {
+                let fields = headers
+                    .iter()
+                    .map(|h| map.get(h).map(cell).unwrap_or_default());
+                writer.write_record(fields)?;
+            }
+            v => writer.write_record([cell(v)])?,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Text of a value inside a CSV cell, nested values are written as JSON.
+pub fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn strip_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k.clone(), strip_nulls(v)))
+            .collect(),
+        Value::Array(arr) => arr
+            .iter()
+            .filter(|v| !v.is_null())
+            .map(strip_nulls)
+            .collect(),
+        v => v.clone(),
+    }
+}
+
+fn write_indented(writer: &mut dyn Write, content: &str, first: &str, rest: &str) -> Result<()> {
+    for (i, line) in content.lines().enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n")?;
+        }
+        let prefix = if i == 0 { first } else { rest };
+        writer.write_all(prefix.as_bytes())?;
+        writer.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn record_writer<'a>(
+    writer: impl FinishWrite + 'a,
+    format: OutputFormat,
+) -> Box<dyn RecordWriter + 'a> {
+    match format {
+        OutputFormat::Json => Box::new(JsonWriter { writer, count: 0 }),
+        OutputFormat::Yaml => Box::new(YamlWriter { writer, count: 0 }),
+        OutputFormat::Toml => Box::new(TomlWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Msgpack => Box::new(MsgpackWriter { writer }),
+        OutputFormat::Csv => delimited_writer(writer, b','),
+        OutputFormat::Tsv => delimited_writer(writer, b'\t'),
+        OutputFormat::Parquet | OutputFormat::Arrow => {
+            Box::new(ColumnarWriter::new(writer, format))
+        }
+    }
+}
+
+pub fn delimited_writer<'a>(
+    writer: impl FinishWrite + 'a,
+    delimiter: u8,
+) -> Box<dyn RecordWriter + 'a> {
+    let writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    Box::new(DelimitedWriter {
+        writer: Some(writer),
+        headers: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_all(format: OutputFormat, records: &[Value]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut writer = record_writer(&mut buf, format);
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(buf)
+    }
+
+    fn players() -> Vec<Value> {
+        vec![
+            json!({"Name": "Buffon", "Kit Number": 77, "Note": null}),
+            json!({"Name": "Dybala, Paulo", "Kit Number": 10, "Note": "captain"}),
+        ]
+    }
+
+    #[test]
+    fn test_toml_writer() -> Result<()> {
+        let content = String::from_utf8(write_all(OutputFormat::Toml, &players())?)?;
+        let parsed: toml::Table = toml::from_str(&content)?;
+        let records = parsed["records"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["Kit Number"].as_integer(), Some(77));
+        assert!(records[0].get("Note").is_none());
+        assert_eq!(records[1]["Note"].as_str(), Some("captain"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_writer() -> Result<()> {
+        let content = String::from_utf8(write_all(OutputFormat::Ndjson, &players())?)?;
+        let lines: Vec<Value> = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines, players());
+        Ok(())
+    }
+
+    #[test]
+    fn test_msgpack_writer() -> Result<()> {
+        let content = write_all(OutputFormat::Msgpack, &players())?;
+        let mut reader = content.as_slice();
+        let first: Value = rmp_serde::from_read(&mut reader)?;
+        let second: Value = rmp_serde::from_read(&mut reader)?;
+        assert_eq!(vec![first, second], players());
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimited_writer() -> Result<()> {
+        let content = String::from_utf8(write_all(OutputFormat::Csv, &players())?)?;
+        assert_eq!(
+            content,
+            "Name,Kit Number,Note\nBuffon,77,\n\"Dybala, Paulo\",10,captain\n"
+        );
+        let content = String::from_utf8(write_all(OutputFormat::Tsv, &players())?)?;
+        assert_eq!(
+            content,
+            "Name\tKit Number\tNote\nBuffon\t77\t\nDybala, Paulo\t10\tcaptain\n"
+        );
+        Ok(())
+    }
+}
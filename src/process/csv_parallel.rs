@@ -0,0 +1,397 @@
+use std::{io::Read, thread};
+
+use anyhow::{anyhow, Result};
+use csv::{Position, StringRecord};
+use rayon::prelude::*;
+use serde_json::Value;
+
+use super::csv_convert::{
+    csv_headers, output_writer, reader_builder, report_errors, RecordConverter, RecordErrors,
+};
+use super::csv_encoding::decode_reader;
+use crate::cli::{CsvOps, CsvReadOps};
+use crate::FinishWrite;
+
+// 每个分块大约的字节数, 一批分块转换出的记录要能放进缓存
+pub(super) const CHUNK_BYTES: usize = 1 << 16;
+
+/// Cuts the input into chunks of whole records.
+///
+/// Only quotes, escapes and comment lines are tracked to find where records end, the
+/// fields themselves are parsed by the workers.
+struct Splitter<R> {
+    reader: R,
+    chunk_bytes: usize,
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    // 已扫描但还不是完整记录的字节, 扫描状态停在它的末尾
+    carry: Vec<u8>,
+    in_quotes: bool,
+    // 上一个字节是结束引号, 再来一个引号就是双写的引号
+    just_closed: bool,
+    escaped: bool,
+    in_comment: bool,
+    line_start: bool,
+    field_start: bool,
+    eof: bool,
+}
+
+impl<R: Read> Splitter<R> {
+    fn new(reader: R, opts: &CsvReadOps, chunk_bytes: usize) -> Self {
+        Self {
+            reader,
+            chunk_bytes,
+            delimiter: opts.delimiter,
+            quote: opts.quote,
+            escape: opts.escape,
+            comment: opts.comment,
+            carry: Vec::new(),
+            in_quotes: false,
+            just_closed: false,
+            escaped: false,
+            in_comment: false,
+            line_start: true,
+            field_start: true,
+            eof: false,
+        }
+    }
+
+    /// Scan `buf[from..]` and return the end of the last complete record in it.
+    fn scan(&mut self, buf: &[u8], from: usize) -> Option<usize> {
+        let mut boundary = None;
+        for (i, &b) in buf.iter().enumerate().skip(from) {
+            let line_start = std::mem::replace(&mut self.line_start, false);
+            let field_start = std::mem::replace(&mut self.field_start, false);
+            let just_closed = std::mem::replace(&mut self.just_closed, false);
+            if self.escaped {
+                self.escaped = false;
+            } else if self.in_comment {
+                self.in_comment = b != b'\n';
+            } else if self.in_quotes {
+                if Some(b) == self.escape {
+                    self.escaped = true;
+                } else if b == self.quote {
+                    self.in_quotes = false;
+                    self.just_closed = true;
+                }
+            } else if b == self.quote && (field_start || just_closed) {
+                // 和 csv 一样, 只有字段开头的引号才开始引用, `5",x` 里的引号是普通字符
+                self.in_quotes = true;
+            } else if line_start && Some(b) == self.comment {
+                self.in_comment = true;
+            } else if b == self.delimiter {
+                self.field_start = true;
+            }
+            if b == b'\n' && !self.in_quotes {
+                // 注释行在换行处结束, 也可以在这里切开
+                self.line_start = true;
+                self.field_start = true;
+                boundary = Some(i + 1);
+            }
+        }
+        boundary
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut buf = std::mem::take(&mut self.carry);
+        let mut boundary = None;
+        while !self.eof && (buf.len() < self.chunk_bytes || boundary.is_none()) {
+            let from = buf.len();
+            buf.resize(from + self.chunk_bytes, 0);
+            let n = self.reader.read(&mut buf[from..])?;
+            buf.truncate(from + n);
+            if n == 0 {
+                self.eof = true;
+            } else if let Some(end) = self.scan(&buf, from) {
+                boundary = Some(end);
+            }
+        }
+        if self.eof {
+            return Ok((!buf.is_empty()).then_some(buf));
+        }
+        let end = boundary.expect("loop ends with a boundary unless at eof");
+        self.carry = buf.split_off(end);
+        Ok(Some(buf))
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+/// A chunk that failed to parse. The position is already relative to the whole
+/// input except for the record number, as earlier chunks may not be parsed yet.
+enum ChunkError {
+    At { pos: Position, error: anyhow::Error },
+    Other(anyhow::Error),
+}
+
+impl ChunkError {
+    /// The error with its position in the whole input, given the number of records
+    /// before the chunk.
+    fn into_error(self, records: u64) -> anyhow::Error {
+        match self {
+            ChunkError::At { pos, error } => error.context(format!(
+                "CSV error: record {} (line {}, byte {})",
+                pos.record() + records,
+                pos.line(),
+                pos.byte()
+            )),
+            ChunkError::Other(e) => e,
+        }
+    }
+}
+
+/// Records parsed from one chunk: the converted values, the conversion errors and the
+/// number of records.
+type Converted = (Vec<Value>, RecordErrors, u64);
+
+/// Parse and convert one chunk starting at `start`, every record must have `fields`
+/// fields like the header.
+fn convert_chunk(
+    chunk: &[u8],
+    start: &Position,
+    fields: usize,
+    converter: &RecordConverter,
+    opts: &CsvReadOps,
+) -> Result<Converted, ChunkError> {
+    // 字段数由这里和表头比较, 而不是和分块的第一行比较
+    let mut reader = reader_builder(opts)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(chunk);
+    let mut values = Vec::new();
+    let mut errors = RecordErrors::default();
+    let mut records = 0;
+    let mut record = StringRecord::new();
+    loop {
+        match reader.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                return Err(match e.kind() {
+                    // csv 报的位置是相对分块的, 只保留错误本身
+                    csv::ErrorKind::Utf8 {
+                        pos: Some(pos),
+                        err,
+                    } => ChunkError::At {
+                        pos: absolute(pos, start),
+                        error: err.clone().into(),
+                    },
+                    _ => ChunkError::Other(e.into()),
+                });
+            }
+        }
+        records += 1;
+        // 报错的位置是相对整个文件的
+        let pos = record.position().map(|pos| absolute(pos, start));
+        if record.len() != fields {
+            return Err(ChunkError::At {
+                pos: pos.unwrap_or_else(Position::new),
+                error: anyhow!(
+                    "found record with {} fields, but the header has {} fields",
+                    record.len(),
+                    fields
+                ),
+            });
+        }
+        record.set_position(pos);
+        match converter.convert(&record) {
+            Ok(Some(value)) => values.push(value),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok((values, errors, records))
+}
+
+fn absolute(pos: &Position, start: &Position) -> Position {
+    let mut ret = Position::new();
+    ret.set_line(pos.line() + start.line() - 1)
+        .set_byte(pos.byte() + start.byte())
+        .set_record(pos.record());
+    ret
+}
+
+/// `process_csv` on a thread pool: chunks of whole records are parsed and converted
+/// in parallel, then written in input order.
+pub(super) fn process_csv_parallel(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvOps,
+    chunk_bytes: usize,
+) -> Result<()> {
+    let threads = match opts.threads {
+        0 => thread::available_parallelism()?.get(),
+        n => n,
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()?;
+    let mut splitter = Splitter::new(
+        decode_reader(reader, opts.read.encoding)?,
+        &opts.read,
+        chunk_bytes,
+    );
+    let mut output = output_writer(writer, opts);
+
+    // 表头在第一个分块里
+    let first = splitter.next_chunk()?.unwrap_or_default();
+    let mut header_reader = reader_builder(&opts.read).from_reader(first.as_slice());
+    let headers = csv_headers(&mut header_reader)?;
+    let fields = headers.len();
+    let (offset, mut records) = if opts.read.header {
+        (header_reader.position().byte() as usize, 1)
+    } else {
+        (0, 0)
+    };
+    let converter = RecordConverter::try_new(headers, opts)?;
+
+    // 下一个分块开始的位置
+    let mut next = Position::new();
+    next.set_line(1 + count_lines(&first[..offset]))
+        .set_byte(offset as u64);
+    let mut chunk = Some(first[offset..].to_vec());
+    let mut pending = Vec::new();
+    let mut errors = RecordErrors::default();
+    loop {
+        // 每批的分块数是线程数的两倍, 内存占用与输入大小无关
+        while pending.len() < threads * 2 {
+            let bytes = match chunk.take() {
+                Some(bytes) => bytes,
+                None => match splitter.next_chunk()? {
+                    Some(bytes) => bytes,
+                    None => break,
+                },
+            };
+            let start = next.clone();
+            next.set_line(start.line() + count_lines(&bytes))
+                .set_byte(start.byte() + bytes.len() as u64);
+            pending.push((start, bytes));
+        }
+        if pending.is_empty() {
+            break;
+        }
+        let results: Vec<_> = pool.install(|| {
+            pending
+                .par_iter()
+                .map(|(start, bytes)| convert_chunk(bytes, start, fields, &converter, &opts.read))
+                .collect()
+        });
+        for result in results {
+            let (values, chunk_errors, count) = result.map_err(|e| e.into_error(records))?;
+            for value in &values {
+                output.write_record(value)?;
+            }
+            errors.append(chunk_errors);
+            records += count;
+        }
+        pending.clear();
+    }
+    output.finish()?;
+
+    report_errors(&errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_csv;
+    use clap::Parser;
+
+    fn convert(input: &str, args: &[&str], chunk_bytes: usize) -> Result<String> {
+        let opts = CsvOps::parse_from(["csv", "-i", "-"].iter().chain(args));
+        let mut buf = Vec::new();
+        if opts.threads == 1 {
+            process_csv(&mut input.as_bytes(), &mut buf, &opts)?;
+        } else {
+            process_csv_parallel(&mut input.as_bytes(), &mut buf, &opts, chunk_bytes)?;
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_splitter() -> Result<()> {
+        let input = "a,b\n\"x\ny\",1\n#\"\n\"q\"\"\n\",2\n5\",6\nlast,3";
+        let mut opts = CsvOps::parse_from(["csv", "-i", "-", "--comment", "#"]).read;
+        opts.header = false;
+        let mut splitter = Splitter::new(input.as_bytes(), &opts, 1);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = splitter.next_chunk()? {
+            chunks.push(String::from_utf8(chunk)?);
+        }
+        assert_eq!(
+            chunks,
+            [
+                "a,b\n",
+                "\"x\ny\",1\n",
+                "#\"\n",
+                "\"q\"\"\n\",2\n",
+                "5\",6\n",
+                "last,3"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_parallel() -> Result<()> {
+        let input = std::fs::read_to_string("assets/juventus.csv")?;
+        for args in [&["--infer"][..], &["--format", "csv", "--header", "false"]] {
+            let expected = convert(&input, args, 0)?;
+            for chunk_bytes in [1, 64, 1 << 20] {
+                let args = [args, &["--threads", "3"]].concat();
+                assert_eq!(convert(&input, &args, chunk_bytes)?, expected);
+            }
+        }
+        assert_eq!(convert("", &["--threads", "2"], 64)?, "[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_parallel_errors() {
+        let input = "Name,Kit Number\nBuffon,77\n\"Dybala\nPaulo\",ten\nChiellini,3\n";
+        let args = ["--schema", "Kit Number=int"];
+        let expected = convert(input, &args, 0).unwrap_err().to_string();
+        let args = [&args[..], &["--threads", "2"]].concat();
+        let err = convert(input, &args, 1).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        assert!(expected.contains("line 3:"));
+
+        // 分块的第一行字段数不对也要和表头比较, 位置是相对整个文件的
+        let input = "Name,Kit Number\nBuffon,77\nChiellini,3\nDybala\nPjanic,5\n";
+        let err = convert(input, &[], 0).unwrap_err();
+        assert!(err.to_string().contains("record 3 (line: 4, byte: 38)"));
+        for chunk_bytes in [1, 20] {
+            let err = convert(input, &["--threads", "2"], chunk_bytes).unwrap_err();
+            assert_eq!(
+                format!("{:#}", err),
+                "CSV error: record 3 (line 4, byte 38): found record with 1 fields, \
+                 but the header has 2 fields"
+            );
+        }
+
+        let input = b"Name,Kit Number\nBuffon,77\nDybala,1\xff\n";
+        for chunk_bytes in [1, 64] {
+            let opts = CsvOps::parse_from(["csv", "-i", "-", "--threads", "2"]);
+            let err = process_csv_parallel(&mut &input[..], &mut Vec::new(), &opts, chunk_bytes)
+                .unwrap_err();
+            assert_eq!(err.to_string(), "CSV error: record 2 (line 3, byte 26)");
+            assert!(err.downcast_ref::<csv::Utf8Error>().is_some());
+        }
+    }
+
+    #[test]
+    fn test_process_csv_parallel_stray_quote() -> Result<()> {
+        // 字段中间的引号是普通字符, 不能让分块以为进入了引用
+        let input = "Name,Height\n".to_string() + &"Buffon,6'4\"\nChiellini,\"1,87\"\n".repeat(50);
+        let expected = convert(&input, &["--format", "csv"], 0)?;
+        for chunk_bytes in [1, 7, 64] {
+            let args = ["--format", "csv", "--threads", "3"];
+            assert_eq!(convert(&input, &args, chunk_bytes)?, expected);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use rusqlite::{params_from_iter, types::ValueRef, Connection};
+use serde_json::{Map, Number, Value};
+
+use super::csv_convert::{csv_headers, csv_reader};
+use super::csv_encoding::check_output_encoding;
+use super::csv_infer::infer_value;
+use super::csv_output::{cell, record_writer};
+use super::csv_show::render_table;
+use crate::cli::{CsvQueryOps, CsvReadOps};
+use crate::FinishWrite;
+
+/// Load each `(name, reader)` as a table of an in-memory SQLite database and run the
+/// query on it.
+///
+/// Cells are stored with the types `--infer` would give them, so numbers compare and
+/// sum as numbers and empty cells are NULL.
+pub fn process_csv_query(
+    tables: &mut [(String, Box<dyn Read>)],
+    writer: &mut dyn FinishWrite,
+    opts: &CsvQueryOps,
+) -> Result<()> {
+    if let Some(format) = opts.format {
+        check_output_encoding(format, opts.read.output_encoding)?;
+    }
+    let mut conn = Connection::open_in_memory()?;
+    for (name, reader) in tables.iter_mut() {
+        load_table(&mut conn, name, reader, &opts.read)
+            .with_context(|| format!("Cannot load table `{}`", name))?;
+    }
+
+    let mut stmt = conn.prepare(&opts.sql)?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.query([])?;
+    let mut next = || -> Result<Option<Vec<Value>>> {
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let values = (0..columns.len())
+            .map(|i| Ok(json_value(row.get_ref(i)?)))
+            .collect::<Result<_>>()?;
+        Ok(Some(values))
+    };
+
+    match opts.format {
+        Some(format) => {
+            let mut output = record_writer(writer, format);
+            while let Some(values) = next()? {
+                let record: Map<String, Value> = columns.iter().cloned().zip(values).collect();
+                output.write_record(&Value::Object(record))?;
+            }
+            output.finish()
+        }
+        None => {
+            let mut table = Vec::new();
+            while let Some(values) = next()? {
+                table.push(values.iter().map(cell).collect());
+            }
+            let mut ret = render_table(&columns, &table, usize::MAX);
+            ret.push_str(&format!("{} rows\n", table.len()));
+            writer.write_all(ret.as_bytes())?;
+            writer.finish()?;
+            Ok(())
+        }
+    }
+}
+
+fn load_table(
+    conn: &mut Connection,
+    name: &str,
+    reader: &mut dyn Read,
+    ops: &CsvReadOps,
+) -> Result<()> {
+    let mut reader = csv_reader(reader, ops)?;
+    let headers = csv_headers(&mut reader)?;
+    let columns: Vec<String> = headers.iter().map(quote_ident).collect();
+
+    // 一个事务里插入所有行, 否则每行都会单独提交
+    let tx = conn.transaction()?;
+    let sql = format!(
+        "CREATE TABLE {} ({})",
+        quote_ident(name),
+        columns.join(", ")
+    );
+    tx.execute(&sql, [])?;
+    {
+        let sql = format!(
+            "INSERT INTO {} VALUES ({})",
+            quote_ident(name),
+            vec!["?"; columns.len()].join(", ")
+        );
+        let mut insert = tx.prepare(&sql)?;
+        for record in reader.records() {
+            let record = record?;
+            let values = (0..columns.len()).map(|i| sql_value(record.get(i).unwrap_or_default()));
+            insert.execute(params_from_iter(values))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_value(field: &str) -> rusqlite::types::Value {
+    use rusqlite::types::Value as Sql;
+    match infer_value(field) {
+        Value::Null => Sql::Null,
+        // sqlite 没有布尔类型, true / false 存为 1 / 0
+        Value::Bool(b) => Sql::Integer(b.into()),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or_default()),
+        },
+        _ => Sql::Text(field.to_string()),
+    }
+}
+
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(s) | ValueRef::Blob(s) => Value::String(String::from_utf8_lossy(s).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use serde_json::json;
+
+    fn query(sql: &str, args: &[&str]) -> Result<String> {
+        let opts =
+            CsvQueryOps::parse_from(["query", sql, "assets/juventus.csv"].iter().chain(args));
+        let kits = "Kit Number,Sponsor\n10,Adidas\n7,Jeep\n";
+        let mut tables: Vec<(String, Box<dyn Read>)> = vec![
+            (
+                "juventus".into(),
+                Box::new(std::fs::File::open("assets/juventus.csv")?),
+            ),
+            ("kits".into(), Box::new(kits.as_bytes())),
+        ];
+        let mut buf = Vec::new();
+        process_csv_query(&mut tables, &mut buf, &opts)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_process_csv_query_group_by() -> Result<()> {
+        let sql = "SELECT Nationality, COUNT(*) AS players, MAX(\"Kit Number\") AS max_kit \
+                   FROM juventus GROUP BY Nationality ORDER BY players DESC, Nationality LIMIT 2";
+        let ret: Value = serde_json::from_str(&query(sql, &["--format", "json"])?)?;
+        assert_eq!(
+            ret,
+            json!([
+                {"Nationality": "Italy", "players": 8, "max_kit": 77},
+                {"Nationality": "Brazil", "players": 3, "max_kit": 13}
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_query_join_table() -> Result<()> {
+        let sql = "SELECT j.Name, k.Sponsor FROM juventus j JOIN kits k USING (\"Kit Number\") \
+                   ORDER BY j.\"Kit Number\"";
+        assert_eq!(
+            query(sql, &[])?,
+            "+-------------------+---------+\n\
+             | Name              | Sponsor |\n\
+             +-------------------+---------+\n\
+             | Cristiano Ronaldo | Jeep    |\n\
+             | Paulo Dybala      | Adidas  |\n\
+             +-------------------+---------+\n\
+             2 rows\n"
+        );
+        assert!(query("SELECT * FROM players", &[]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use std::{collections::HashSet, fmt::Write as _, io::Read};
+
+use anyhow::Result;
+use csv::StringRecord;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::csv_convert::{csv_headers, csv_reader};
+use crate::cli::CsvShowOps;
+
+#[derive(Debug, Default)]
+struct ColumnStats {
+    count: usize,
+    nulls: usize,
+    distinct: HashSet<String>,
+    // 所有非空值都是数字时才有 min / max
+    numeric: bool,
+    min: f64,
+    max: f64,
+}
+
+impl ColumnStats {
+    fn new() -> Self {
+        Self {
+            numeric: true,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            ..Default::default()
+        }
+    }
+
+    fn update(&mut self, field: &str) {
+        if field.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.count += 1;
+        if !self.distinct.contains(field) {
+            self.distinct.insert(field.to_string());
+        }
+        match field.parse::<f64>() {
+            Ok(n) if self.numeric && n.is_finite() => {
+                self.min = self.min.min(n);
+                self.max = self.max.max(n);
+            }
+            _ => self.numeric = false,
+        }
+    }
+
+    fn row(&self, name: &str) -> Vec<String> {
+        let (min, max) = if self.numeric && self.count > 0 {
+            (self.min.to_string(), self.max.to_string())
+        } else {
+            ("-".to_string(), "-".to_string())
+        };
+        vec![
+            name.to_string(),
+            self.count.to_string(),
+            self.nulls.to_string(),
+            self.distinct.len().to_string(),
+            min,
+            max,
+        ]
+    }
+}
+
+/// Render the CSV as an aligned table, one page of `--limit` rows at a time, or
+/// per-column statistics with `--stats`.
+pub fn process_csv_show(reader: &mut dyn Read, opts: &CsvShowOps) -> Result<String> {
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let headers: Vec<String> = csv_headers(&mut reader)?.iter().map(String::from).collect();
+
+    let skip = opts.limit * (opts.page.max(1) - 1);
+    let mut rows = Vec::new();
+    let mut stats: Vec<ColumnStats> = headers.iter().map(|_| ColumnStats::new()).collect();
+    let mut total = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if opts.stats {
+            for (stat, field) in stats.iter_mut().zip(record.iter()) {
+                stat.update(field);
+            }
+        } else if total >= skip && rows.len() < opts.limit {
+            rows.push(record.iter().map(String::from).collect());
+        }
+        total += 1;
+    }
+
+    if opts.stats {
+        let names = ["column", "count", "nulls", "distinct", "min", "max"];
+        let rows: Vec<_> = stats.iter().zip(&headers).map(|(s, h)| s.row(h)).collect();
+        let mut ret = render_table(&names.map(String::from), &rows, opts.max_width);
+        writeln!(ret, "{} rows", total)?;
+        return Ok(ret);
+    }
+
+    let mut ret = render_table(&headers, &rows, opts.max_width);
+    if rows.is_empty() {
+        writeln!(ret, "{} rows", total)?;
+    } else {
+        let pages = total.div_ceil(opts.limit.max(1));
+        writeln!(
+            ret,
+            "{} rows, showing {}-{} (page {}/{})",
+            total,
+            skip + 1,
+            skip + rows.len(),
+            opts.page.max(1),
+            pages
+        )?;
+    }
+    Ok(ret)
+}
+
+/// Draw rows as a bordered table. Cells wider than `max_width` are truncated with `…`
+/// and numeric columns are right aligned.
+pub fn render_table(headers: &[String], rows: &[Vec<String>], max_width: usize) -> String {
+    let cell = |s: &str| truncate(&s.replace(['\n', '\r'], " "), max_width.max(1));
+    let headers: Vec<String> = headers.iter().map(|h| cell(h)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| cell(c)).collect())
+        .collect();
+
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .chain([&headers[i]])
+                .map(|c| c.width())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let numeric: Vec<bool> = (0..headers.len())
+        .map(|i| {
+            let mut values = rows
+                .iter()
+                .filter_map(|row| row.get(i))
+                .filter(|c| !c.is_empty());
+            values.clone().next().is_some() && values.all(|c| c.parse::<f64>().is_ok())
+        })
+        .collect();
+
+    let border: String = widths
+        .iter()
+        .map(|w| format!("+{}", "-".repeat(w + 2)))
+        .chain(["+\n".to_string()])
+        .collect();
+    let line = |row: &[String], align: &[bool]| {
+        let mut s = String::new();
+        for (i, w) in widths.iter().enumerate() {
+            let c = row.get(i).map(String::as_str).unwrap_or_default();
+            let pad = " ".repeat(w - c.width());
+            if align.get(i) == Some(&true) {
+                s.push_str(&format!("| {}{} ", pad, c));
+            } else {
+                s.push_str(&format!("| {}{} ", c, pad));
+            }
+        }
+        s.push_str("|\n");
+        s
+    };
+
+    let mut ret = border.clone();
+    ret.push_str(&line(&headers, &[]));
+    ret.push_str(&border);
+    for row in &rows {
+        ret.push_str(&line(row, &numeric));
+    }
+    if !rows.is_empty() {
+        ret.push_str(&border);
+    }
+    ret
+}
+
+fn truncate(s: &str, max_width: usize) -> String {
+    if s.width() <= max_width {
+        return s.to_string();
+    }
+    let mut ret = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or_default();
+        // 留一格给省略号
+        if width + w > max_width - 1 {
+            break;
+        }
+        ret.push(c);
+        width += w;
+    }
+    ret.push('…');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+    use clap::Parser;
+
+    fn show(args: &[&str]) -> Result<String> {
+        let opts = CsvShowOps::parse_from(["show"].iter().chain(args));
+        let mut reader = get_reader("assets/juventus.csv")?;
+        process_csv_show(&mut reader, &opts)
+    }
+
+    #[test]
+    fn test_render_table() {
+        let headers = ["Name".to_string(), "Kit".to_string()];
+        let rows = vec![
+            vec!["Higuaín".to_string(), "21".to_string()],
+            vec!["武磊".to_string(), "7".to_string()],
+            vec!["Wojciech Szczesny".to_string(), "1".to_string()],
+        ];
+        assert_eq!(
+            render_table(&headers, &rows, 10),
+            "+------------+-----+\n\
+             | Name       | Kit |\n\
+             +------------+-----+\n\
+             | Higuaín    |  21 |\n\
+             | 武磊       |   7 |\n\
+             | Wojciech … |   1 |\n\
+             +------------+-----+\n"
+        );
+    }
+
+    #[test]
+    fn test_process_csv_show_pages() -> Result<()> {
+        let ret = show(&["--limit", "2", "--page", "2", "--max-width", "12"])?;
+        assert_eq!(
+            ret,
+            "+--------------+------------+--------------+-------------+------------+\n\
+             | Name         | Position   | DOB          | Nationality | Kit Number |\n\
+             +--------------+------------+--------------+-------------+------------+\n\
+             | Gianluigi B… | Goalkeeper | Jan 28, 197… | Italy       |         77 |\n\
+             | Carlo Pinso… | Goalkeeper | Mar 16, 199… | Italy       |         31 |\n\
+             +--------------+------------+--------------+-------------+------------+\n\
+             27 rows, showing 3-4 (page 2/14)\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_show_stats() -> Result<()> {
+        let ret = show(&["--stats"])?;
+        let lines: Vec<&str> = ret.lines().collect();
+        assert_eq!(
+            lines[1],
+            "| column      | count | nulls | distinct | min | max |"
+        );
+        assert_eq!(
+            lines[7],
+            "| Kit Number  |    27 |     0 |       27 | 1   | 77  |"
+        );
+        assert_eq!(
+            lines[6],
+            "| Nationality |    27 |     0 |       14 | -   | -   |"
+        );
+        assert_eq!(lines[9], "27 rows");
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    fs::{self, File},
+    io::Read,
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter, Writer};
+use tempfile::TempDir;
+
+use super::csv_convert::{csv_headers, csv_reader, csv_writer};
+use super::csv_infer::parse_date;
+use crate::cli::CsvSortOps;
+use crate::FinishWrite;
+
+/// Value a cell is compared by: numbers numerically, dates chronologically and
+/// everything else as text. Empty cells come first, then numbers, dates and text.
+#[derive(Debug)]
+pub enum SortKey {
+    Empty,
+    Number(f64),
+    // 日期之后的内容(例如时间)作为同一天内的次序
+    Date(NaiveDate, String),
+    Text(String),
+}
+
+impl SortKey {
+    fn parse(field: &str) -> Self {
+        if field.is_empty() {
+            return SortKey::Empty;
+        }
+        if let Ok(n) = field.trim().parse::<f64>() {
+            if n.is_finite() {
+                return SortKey::Number(n);
+            }
+        }
+        match parse_date(field) {
+            Some((date, _, rest)) => SortKey::Date(date, rest.to_string()),
+            None => SortKey::Text(field.to_string()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SortKey::Empty => 0,
+            SortKey::Number(_) => 1,
+            SortKey::Date(..) => 2,
+            SortKey::Text(_) => 3,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::Date(a, ra), SortKey::Date(b, rb)) => a.cmp(b).then_with(|| ra.cmp(rb)),
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+struct Sorter {
+    by: Vec<usize>,
+    desc: bool,
+}
+
+impl Sorter {
+    fn key(&self, record: &StringRecord) -> Vec<SortKey> {
+        sort_key(record, &self.by)
+    }
+
+    fn compare(&self, a: &[SortKey], b: &[SortKey]) -> Ordering {
+        let ord = a.cmp(b);
+        if self.desc {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// Typed key of the given columns, in the order `csv sort` puts rows.
+pub fn sort_key(record: &StringRecord, columns: &[usize]) -> Vec<SortKey> {
+    columns
+        .iter()
+        .map(|&i| SortKey::parse(record.get(i).unwrap_or_default()))
+        .collect()
+}
+
+/// The next record of a sorted run, waiting to be merged.
+struct Head<'a> {
+    key: Vec<SortKey>,
+    run: usize,
+    record: StringRecord,
+    sorter: &'a Sorter,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap 是最大堆, 反转后先弹出最小的; 相同的键按 run 的先后保持输入顺序
+        self.sorter
+            .compare(&self.key, &other.key)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+// 一次最多同时打开合并的 run 数, 更多时分几轮合并
+const MAX_FAN_IN: usize = 64;
+
+/// Sorted runs spilled to a temporary directory, removed when dropped.
+struct Runs {
+    dir: TempDir,
+    paths: Vec<PathBuf>,
+    // 已写过的 run 个数, 用于生成不重复的文件名
+    count: usize,
+}
+
+impl Runs {
+    fn new(temp_dir: Option<&str>) -> Result<Self> {
+        let dir = match temp_dir {
+            Some(dir) => TempDir::new_in(dir)?,
+            None => TempDir::new()?,
+        };
+        Ok(Self {
+            dir,
+            paths: Vec::new(),
+            count: 0,
+        })
+    }
+
+    fn next_path(&mut self) -> PathBuf {
+        self.count += 1;
+        self.dir.path().join(format!("run-{}.csv", self.count))
+    }
+
+    fn spill(&mut self, rows: Vec<(Vec<SortKey>, StringRecord)>) -> Result<()> {
+        let path = self.next_path();
+        let mut writer = Writer::from_path(&path)?;
+        for (_, record) in rows {
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        self.paths.push(path);
+        Ok(())
+    }
+
+    /// Merge groups of adjacent runs until at most `MAX_FAN_IN` are left. Adjacent
+    /// runs keep their order, so the sort stays stable.
+    fn reduce(&mut self, sorter: &Sorter) -> Result<()> {
+        while self.paths.len() > MAX_FAN_IN {
+            let groups: Vec<Vec<PathBuf>> = self
+                .paths
+                .chunks(MAX_FAN_IN)
+                .map(<[PathBuf]>::to_vec)
+                .collect();
+            let mut paths = Vec::with_capacity(groups.len());
+            for group in groups {
+                let path = self.next_path();
+                let mut writer = Writer::from_path(&path)?;
+                merge(open(&group)?, sorter, |record| {
+                    writer.write_record(record)?;
+                    Ok(())
+                })?;
+                writer.flush()?;
+                for merged in &group {
+                    fs::remove_file(merged)?;
+                }
+                paths.push(path);
+            }
+            self.paths = paths;
+        }
+        Ok(())
+    }
+}
+
+fn open(paths: &[PathBuf]) -> Result<Vec<StringRecordsIntoIter<File>>> {
+    paths
+        .iter()
+        .map(|path| {
+            let reader = ReaderBuilder::new().has_headers(false).from_path(path)?;
+            Ok(reader.into_records())
+        })
+        .collect()
+}
+
+/// Merge sorted runs into one sorted sequence handed to `emit`, ties in run order.
+fn merge(
+    mut readers: Vec<StringRecordsIntoIter<File>>,
+    sorter: &Sorter,
+    mut emit: impl FnMut(&StringRecord) -> Result<()>,
+) -> Result<()> {
+    let mut heap = BinaryHeap::new();
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(record) = reader.next() {
+            let record = record?;
+            heap.push(Head {
+                key: sorter.key(&record),
+                run,
+                record,
+                sorter,
+            });
+        }
+    }
+    while let Some(head) = heap.pop() {
+        emit(&head.record)?;
+        if let Some(record) = readers[head.run].next() {
+            let record = record?;
+            heap.push(Head {
+                key: sorter.key(&record),
+                record,
+                ..head
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Sort rows by `--by` columns with a typed comparison, optionally keeping only the
+/// first row for each `--dedup-by` key.
+///
+/// At most `--buffer-rows` rows are held in memory: when the input is larger, sorted
+/// runs are written to a temporary directory and merged, at most 64 at a time. The sort is stable, and the
+/// keys seen by `--dedup-by` are kept in memory.
+pub fn process_csv_sort(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &CsvSortOps,
+) -> Result<()> {
+    let mut reader = csv_reader(reader, &opts.read)?;
+    let headers = csv_headers(&mut reader)?;
+    let index = |name: &String| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow!("Column `{}` not found in the input", name))
+    };
+
+    // 只指定 --dedup-by 时按去重的列排序
+    let by = if opts.by.is_empty() {
+        &opts.dedup_by
+    } else {
+        &opts.by
+    };
+    if by.is_empty() {
+        bail!("Nothing to sort by, use --by or --dedup-by");
+    }
+    let sorter = Sorter {
+        by: by.iter().map(index).collect::<Result<_>>()?,
+        desc: opts.desc,
+    };
+    let dedup: Vec<usize> = opts.dedup_by.iter().map(index).collect::<Result<_>>()?;
+    let buffer_rows = opts.buffer_rows.max(1);
+
+    let mut rows = Vec::new();
+    let mut runs: Option<Runs> = None;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        rows.push((sorter.key(&record), record.clone()));
+        if rows.len() >= buffer_rows {
+            rows.sort_by(|a, b| sorter.compare(&a.0, &b.0));
+            let runs = match &mut runs {
+                Some(runs) => runs,
+                None => runs.insert(Runs::new(opts.temp_dir.as_deref())?),
+            };
+            runs.spill(std::mem::take(&mut rows))?;
+        }
+    }
+    rows.sort_by(|a, b| sorter.compare(&a.0, &b.0));
+
+    let mut output = csv_writer(&mut *writer, &opts.read);
+    if opts.read.header {
+        output.write_record(&headers)?;
+    }
+    let mut seen = HashSet::new();
+    let mut emit = |record: &StringRecord| -> Result<()> {
+        if !dedup.is_empty() {
+            let key: Vec<String> = dedup
+                .iter()
+                .map(|&i| record.get(i).unwrap_or_default().to_string())
+                .collect();
+            if !seen.insert(key) {
+                return Ok(());
+            }
+        }
+        output.write_record(record)?;
+        Ok(())
+    };
+
+    match runs {
+        // 全部放得进内存, 不需要合并
+        None => {
+            for (_, record) in &rows {
+                emit(record)?;
+            }
+        }
+        Some(mut runs) => {
+            if !rows.is_empty() {
+                runs.spill(rows)?;
+            }
+            runs.reduce(&sorter)?;
+            merge(open(&runs.paths)?, &sorter, emit)?;
+        }
+    }
+    output.flush()?;
+    drop(output);
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+    use clap::Parser;
+
+    fn sort(reader: &mut dyn Read, args: &[&str]) -> Result<String> {
+        let opts = CsvSortOps::parse_from(["sort"].iter().chain(args));
+        let mut buf = Vec::new();
+        process_csv_sort(reader, &mut buf, &opts)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_process_csv_sort_typed() -> Result<()> {
+        let input = "Name,Kit\nA,10\nB,9\nC,\nD,100\nE,9\n";
+        assert_eq!(
+            sort(&mut input.as_bytes(), &["--by", "Kit"])?,
+            "Name,Kit\nC,\nB,9\nE,9\nA,10\nD,100\n"
+        );
+        assert_eq!(
+            sort(&mut input.as_bytes(), &["--by", "Kit", "--desc"])?,
+            "Name,Kit\nD,100\nA,10\nB,9\nE,9\nC,\n"
+        );
+        assert_eq!(
+            sort(&mut input.as_bytes(), &["--dedup-by", "Kit"])?,
+            "Name,Kit\nC,\nB,9\nA,10\nD,100\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_sort_external() -> Result<()> {
+        let args = ["--by", "DOB", "--desc", "--dedup-by", "Nationality"];
+        let in_memory = sort(&mut get_reader("assets/juventus.csv")?, &args)?;
+        let args = [&args[..], &["--buffer-rows", "4"]].concat();
+        let merged = sort(&mut get_reader("assets/juventus.csv")?, &args)?;
+        assert_eq!(merged, in_memory);
+
+        let lines: Vec<&str> = merged.lines().collect();
+        // 14 nationalities, youngest player first
+        assert_eq!(lines.len(), 15);
+        assert!(lines[1].starts_with("Matthijs de Ligt,"));
+        assert!(lines[2].starts_with("Merih Demiral,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_sort_many_runs() -> Result<()> {
+        // 300 个 run, 要先合并成 5 个再输出
+        let mut input = "Id,Key\n".to_string();
+        for i in 0..600 {
+            input.push_str(&format!("{},{}\n", i, (i * 7) % 10));
+        }
+        let merged = sort(
+            &mut input.as_bytes(),
+            &["--by", "Key", "--buffer-rows", "2"],
+        )?;
+        let in_memory = sort(&mut input.as_bytes(), &["--by", "Key"])?;
+        assert_eq!(merged, in_memory);
+        let lines: Vec<&str> = merged.lines().collect();
+        assert_eq!(&lines[1..4], ["0,0", "10,0", "20,0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_sort_errors() {
+        let input = "Name,Kit\nA,10\n";
+        assert!(sort(&mut input.as_bytes(), &[]).is_err());
+        assert!(sort(&mut input.as_bytes(), &["--by", "Club"]).is_err());
+        assert!(sort(
+            &mut input.as_bytes(),
+            &["--by", "Kit", "--dedup-by", "Club"]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+use anyhow::{anyhow, Context, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::csv_convert::{csv_headers, reader_builder};
+use super::csv_encoding::decode_reader;
+use super::csv_infer::parse_value;
+use super::csv_output::cell;
+use crate::cli::{CellType, CsvValidateOps};
+use crate::FinishWrite;
+
+/// The schema file, e.g.
+///
+/// ```yaml
+/// columns:
+///   - name: Kit Number
+///     required: true
+///     type: int
+///     min: 1
+///     max: 99
+///     unique: true
+///   - name: Position
+///     enum: [Goalkeeper, Centre-Back, Second Striker]
+///   - name: Name
+///     pattern: "[A-Z][a-z]+ [A-Z].*"
+/// unique:
+///   - [Name, DOB]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Schema {
+    #[serde(default)]
+    columns: Vec<ColumnSchema>,
+    /// Columns that must be unique together
+    #[serde(default)]
+    unique: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnSchema {
+    name: String,
+    #[serde(default)]
+    required: bool,
+    #[serde(rename = "type")]
+    ty: Option<String>,
+    /// The whole value must match
+    pattern: Option<String>,
+    #[serde(rename = "enum")]
+    values: Option<Vec<Value>>,
+    #[serde(default)]
+    unique: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+/// One rule violation. `row` counts data rows from 1, `line` is the line in the file.
+#[derive(Debug, Serialize)]
+struct Violation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<String>,
+    rule: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    message: String,
+}
+
+impl Violation {
+    fn missing_column(name: &str) -> Self {
+        Self {
+            row: None,
+            line: None,
+            column: Some(name.to_string()),
+            rule: "column",
+            value: None,
+            message: format!("Column `{}` not found in the input", name),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    valid: bool,
+    rows: usize,
+    errors: Vec<Violation>,
+}
+
+struct Rule {
+    name: String,
+    index: usize,
+    required: bool,
+    ty: Option<CellType>,
+    pattern: Option<Regex>,
+    values: Option<HashSet<String>>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+/// Columns that must be unique together and the row each key was first seen on.
+struct UniqueKey {
+    names: Vec<String>,
+    indices: Vec<usize>,
+    seen: HashMap<Vec<String>, usize>,
+}
+
+/// Check every row against the schema and write a JSON report of the violations.
+///
+/// Returns the number of violations, a schema that cannot be parsed is an error.
+pub fn process_csv_validate(
+    reader: &mut dyn Read,
+    schema: &[u8],
+    writer: &mut dyn FinishWrite,
+    opts: &CsvValidateOps,
+) -> Result<usize> {
+    let schema: Schema = serde_yaml::from_slice(schema).context("Invalid schema")?;
+    // 允许行的字段数不一致, 由校验报告出来
+    let reader = decode_reader(reader, opts.read.encoding)?;
+    let mut reader = reader_builder(&opts.read)
+        .flexible(true)
+        .from_reader(reader);
+    let headers = csv_headers(&mut reader)?;
+
+    let mut errors = Vec::new();
+    let index = |name: &str| headers.iter().position(|h| h == name);
+
+    let mut rules = Vec::new();
+    let mut keys = Vec::new();
+    for column in schema.columns {
+        let Some(i) = index(&column.name) else {
+            errors.push(Violation::missing_column(&column.name));
+            continue;
+        };
+        if column.unique {
+            keys.push(vec![column.name.clone()]);
+        }
+        rules.push(Rule::try_new(column, i)?);
+    }
+    keys.extend(schema.unique);
+    let mut keys: Vec<UniqueKey> = keys
+        .into_iter()
+        .filter_map(|names| {
+            let indices: Option<Vec<usize>> = names.iter().map(|n| index(n)).collect();
+            if indices.is_none() {
+                for name in names.iter().filter(|n| index(n).is_none()) {
+                    errors.push(Violation::missing_column(name));
+                }
+            }
+            Some(UniqueKey {
+                indices: indices?,
+                names,
+                seen: HashMap::new(),
+            })
+        })
+        .collect();
+
+    let mut rows = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        rows += 1;
+        let line = record.position().map(|p| p.line());
+        let mut violation = |column: Option<&str>, rule, value: Option<&str>, message| {
+            errors.push(Violation {
+                row: Some(rows),
+                line,
+                column: column.map(String::from),
+                rule,
+                value: value.map(String::from),
+                message,
+            })
+        };
+
+        if record.len() != headers.len() {
+            let message = format!("Expected {} fields, found {}", headers.len(), record.len());
+            violation(None, "columns", None, message);
+        }
+        for rule in &rules {
+            let value = record.get(rule.index).unwrap_or_default();
+            for (name, message) in rule.check(value) {
+                violation(Some(&rule.name), name, Some(value), message);
+            }
+        }
+        for key in &mut keys {
+            let values: Vec<String> = key
+                .indices
+                .iter()
+                .map(|&i| record.get(i).unwrap_or_default().to_string())
+                .collect();
+            // 空的 key 交给 required 检查
+            if values.iter().all(|v| v.is_empty()) {
+                continue;
+            }
+            match key.seen.get(&values) {
+                Some(first) => {
+                    let message = format!("Duplicate of row {}", first);
+                    let value = values.join(", ");
+                    violation(Some(&key.names.join(", ")), "unique", Some(&value), message);
+                }
+                None => {
+                    key.seen.insert(values, rows);
+                }
+            }
+        }
+    }
+
+    let count = errors.len();
+    let report = Report {
+        valid: errors.is_empty(),
+        rows,
+        errors,
+    };
+    serde_json::to_writer_pretty(&mut *writer, &report)?;
+    writer.write_all(b"\n")?;
+    writer.finish()?;
+    Ok(count)
+}
+
+impl Rule {
+    fn try_new(column: ColumnSchema, index: usize) -> Result<Self> {
+        let context = || format!("Invalid rule for column `{}`", column.name);
+        let ty = column
+            .ty
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .with_context(context)?;
+        let pattern = column
+            .pattern
+            .as_deref()
+            .map(|p| Regex::new(&format!("^(?:{})$", p)))
+            .transpose()
+            .with_context(context)?;
+        if let (Some(min), Some(max)) = (column.min, column.max) {
+            if min > max {
+                return Err(anyhow!("min {} is greater than max {}", min, max))
+                    .with_context(context);
+            }
+        }
+        Ok(Self {
+            index,
+            required: column.required,
+            ty,
+            pattern,
+            values: column.values.map(|v| v.iter().map(cell).collect()),
+            min: column.min,
+            max: column.max,
+            name: column.name,
+        })
+    }
+
+    /// The rules `value` breaks and why, empty values only break `required`.
+    fn check(&self, value: &str) -> Vec<(&'static str, String)> {
+        let mut ret = Vec::new();
+        if value.is_empty() {
+            if self.required {
+                ret.push(("required", "Value is required".to_string()));
+            }
+            return ret;
+        }
+        if let Some(ty) = self.ty {
+            if let Err(e) = parse_value(value, ty) {
+                ret.push(("type", e.to_string()));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(value) {
+                ret.push(("pattern", format!("`{}` does not match the pattern", value)));
+            }
+        }
+        if let Some(values) = &self.values {
+            if !values.contains(value) {
+                ret.push((
+                    "enum",
+                    format!("`{}` is not one of the allowed values", value),
+                ));
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            // NaN 和任何数比较都是 false, 会绕过范围检查
+            match value.trim().parse::<f64>().ok().filter(|n| n.is_finite()) {
+                Some(n) if self.min.is_some_and(|min| n < min) => {
+                    let message =
+                        format!("{} is less than the minimum {}", value, self.min.unwrap());
+                    ret.push(("range", message));
+                }
+                Some(n) if self.max.is_some_and(|max| n > max) => {
+                    let message = format!(
+                        "{} is greater than the maximum {}",
+                        value,
+                        self.max.unwrap()
+                    );
+                    ret.push(("range", message));
+                }
+                Some(_) => {}
+                None => ret.push(("range", format!("`{}` is not a number", value))),
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use serde_json::json;
+
+    const SCHEMA: &str = r#"
+columns:
+  - name: Name
+    required: true
+    pattern: "[A-Z][a-z]+ [A-Z][a-z]+"
+  - name: Position
+    enum: [Goalkeeper, Centre-Back, Second Striker]
+  - name: Kit Number
+    type: int
+    min: 1
+    max: 99
+    unique: true
+unique:
+  - [Name, Position]
+"#;
+
+    fn validate(input: &str, schema: &str) -> Result<(usize, Value)> {
+        let opts = CsvValidateOps::parse_from(["validate", "-s", "Cargo.toml"]);
+        let mut buf = Vec::new();
+        let count =
+            process_csv_validate(&mut input.as_bytes(), schema.as_bytes(), &mut buf, &opts)?;
+        Ok((count, serde_json::from_slice(&buf)?))
+    }
+
+    #[test]
+    fn test_process_csv_validate_valid() -> Result<()> {
+        let input = std::fs::read_to_string("assets/juventus.csv")?;
+        let schema = "columns:\n  - name: Kit Number\n    type: int\n    unique: true\n";
+        let (count, report) = validate(&input, schema)?;
+        assert_eq!(count, 0);
+        assert_eq!(report, json!({"valid": true, "rows": 27, "errors": []}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_validate_violations() -> Result<()> {
+        let input = "Name,Position,Kit Number\n\
+                     Mattia Perin,Goalkeeper,37\n\
+                     ,Goalkeeper,120\n\
+                     paulo dybala,Striker,ten\n\
+                     Mattia Perin,Goalkeeper,37,extra\n";
+        let (count, report) = validate(input, SCHEMA)?;
+        assert_eq!(count, 9);
+        assert_eq!(report["valid"], false);
+        assert_eq!(report["rows"], 4);
+        let errors: Vec<_> = report["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                (
+                    e["row"].as_u64().unwrap(),
+                    e["column"].as_str(),
+                    e["rule"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, Some("Name"), "required"),
+                (2, Some("Kit Number"), "range"),
+                (3, Some("Name"), "pattern"),
+                (3, Some("Position"), "enum"),
+                (3, Some("Kit Number"), "type"),
+                (3, Some("Kit Number"), "range"),
+                (4, None, "columns"),
+                (4, Some("Kit Number"), "unique"),
+                (4, Some("Name, Position"), "unique"),
+            ]
+        );
+        assert_eq!(
+            report["errors"][1],
+            json!({
+                "row": 2,
+                "line": 3,
+                "column": "Kit Number",
+                "rule": "range",
+                "value": "120",
+                "message": "120 is greater than the maximum 99"
+            })
+        );
+        assert_eq!(report["errors"][7]["message"], "Duplicate of row 1");
+
+        let schema = "columns:\n  - name: Rating\n    min: 0\n    max: 3\n";
+        let (count, report) = validate("Rating\nNaN\ninf\n2\n", schema)?;
+        assert_eq!(count, 2);
+        assert_eq!(report["errors"][0]["message"], "`NaN` is not a number");
+        assert_eq!(report["errors"][1]["message"], "`inf` is not a number");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_validate_schema_errors() -> Result<()> {
+        let input = "Name,Position\nMattia Perin,Goalkeeper\n";
+        let (count, report) = validate(input, "columns:\n  - name: Club\n    required: true\n")?;
+        assert_eq!(count, 1);
+        assert_eq!(report["errors"][0]["rule"], "column");
+        assert_eq!(
+            report["errors"][0]["message"],
+            "Column `Club` not found in the input"
+        );
+
+        assert!(validate(input, "columns:\n  - name: Name\n    type: money\n").is_err());
+        assert!(validate(input, "columns:\n  - name: Name\n    pattern: \"[\"\n").is_err());
+        assert!(validate(input, "colums: []\n").is_err());
+        Ok(())
+    }
+}
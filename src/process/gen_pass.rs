@@ -1,52 +1,679 @@
-use rand::seq::SliceRandom;
-// use zxcvbn::zxcvbn;
+use std::collections::HashSet;
 
-const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
+use anyhow::{bail, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde_json::json;
+use zxcvbn::zxcvbn;
+
+use super::csv_output::record_writer;
+use crate::cli::{Capitalize, Charset, GenPassOps, OutputFormat, PasswordPolicy};
+use crate::FinishWrite;
+
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const NUMBER: &[u8] = b"0123456789";
 const SYMBOL: &[u8] = b"!@#$%^&*_";
+// 容易看错的字符, 除非 --include-ambiguous 否则不用; base58 正好也不含它们
+const AMBIGUOUS: &[u8] = b"IOl0";
 
-pub fn process_genpass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
+// https://www.eff.org/dice, 7776 个词, 每个词约 12.9 bit
+const EFF_WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+const DEFAULT_WORDS: usize = 6;
+// 达不到 --min-score 或 --max-repeat 时最多重新生成的次数
+const MAX_ATTEMPTS: usize = 1000;
+
+/// What `process_genpass` draws a password from and the policy it must meet, the
+/// password half of `GenPassOps` without the command line.
+#[derive(Debug, Clone)]
+pub struct GenPassOptions {
+    pub length: u8,
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+    pub charset: Option<Charset>,
+    pub chars: Option<String>,
+    pub symbols: Option<String>,
+    pub include_ambiguous: bool,
+    pub policy: PasswordPolicy,
+}
+
+impl GenPassOptions {
+    /// `length` characters from all four classes, at least one of each, without the
+    /// ambiguous ones.
+    pub fn new(length: u8) -> Self {
+        Self {
+            length,
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+            charset: None,
+            chars: None,
+            symbols: None,
+            include_ambiguous: false,
+            policy: PasswordPolicy::default(),
+        }
+    }
+}
+
+impl From<&GenPassOps> for GenPassOptions {
+    fn from(opts: &GenPassOps) -> Self {
+        Self {
+            length: opts.length,
+            upper: opts.upper,
+            lower: opts.lower,
+            number: opts.number,
+            symbol: opts.symbol,
+            charset: opts.charset,
+            chars: opts.chars.clone(),
+            symbols: opts.symbols.clone(),
+            include_ambiguous: opts.include_ambiguous,
+            policy: opts.policy.clone(),
+        }
+    }
+}
+
+/// What `process_passphrase` draws a passphrase from and the policy it must meet, the
+/// passphrase half of `GenPassOps` without the command line.
+#[derive(Debug, Clone)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: Capitalize,
+    pub append_digit: bool,
+    pub append_symbol: bool,
+    pub symbols: Option<String>,
+    pub policy: PasswordPolicy,
+}
+
+impl PassphraseOptions {
+    /// `words` lowercase words joined by `-`.
+    pub fn new(words: usize) -> Self {
+        Self {
+            words,
+            separator: "-".to_string(),
+            capitalize: Capitalize::None,
+            append_digit: false,
+            append_symbol: false,
+            symbols: None,
+            policy: PasswordPolicy::default(),
+        }
+    }
+}
+
+impl From<&GenPassOps> for PassphraseOptions {
+    fn from(opts: &GenPassOps) -> Self {
+        Self {
+            words: opts.words.unwrap_or(DEFAULT_WORDS),
+            separator: opts.separator.clone(),
+            capitalize: opts.capitalize,
+            append_digit: opts.append_digit,
+            append_symbol: opts.append_symbol,
+            symbols: opts.symbols.clone(),
+            policy: opts.policy.clone(),
+        }
+    }
+}
+
+/// Generate a random password of `opts.length` characters that meets `opts.policy`.
+///
+/// Returns the password and its entropy in bits. A policy no password can meet is an
+/// error up front, one that is merely hard to meet fails after `MAX_ATTEMPTS` tries.
+pub fn process_genpass(opts: &GenPassOptions) -> Result<(String, f64)> {
+    let policy = &opts.policy;
+    let length = opts.length as usize;
+    if let Some(min) = policy.min_length {
+        if opts.length < min {
+            bail!(
+                "--length {} is shorter than the policy minimum of {}",
+                opts.length,
+                min
+            );
+        }
+    }
+
+    if opts.include_ambiguous && opts.charset == Some(Charset::Base58) {
+        bail!("--charset base58 never includes ambiguous characters, drop --include-ambiguous");
+    }
+    let symbols = opts.symbols.as_deref().map_or(SYMBOL, str::as_bytes);
+    let none: &[u8] = b"";
+    // --chars 按类别拆开, 每个出现的类别仍然至少用到一次
+    let literal = opts.chars.as_deref().map(str::as_bytes).unwrap_or_default();
+    let class_of = |f: fn(&u8) -> bool| -> Vec<u8> { literal.iter().copied().filter(f).collect() };
+    let literal_classes = (
+        class_of(u8::is_ascii_uppercase),
+        class_of(u8::is_ascii_lowercase),
+        class_of(u8::is_ascii_digit),
+        class_of(u8::is_ascii_punctuation),
+    );
+    let (upper, lower, number, symbol) = match opts.charset {
+        _ if opts.chars.is_some() => (
+            literal_classes.0.as_slice(),
+            literal_classes.1.as_slice(),
+            literal_classes.2.as_slice(),
+            literal_classes.3.as_slice(),
+        ),
+        None => (UPPER, LOWER, NUMBER, symbols),
+        Some(Charset::Alnum | Charset::Base58) => (UPPER, LOWER, NUMBER, none),
+        Some(Charset::Hex) => (none, &LOWER[..6], NUMBER, none),
+        Some(Charset::Pin) => (none, none, NUMBER, none),
+    };
+    // 预设或 --chars 没有的类别, 要求至少一个时报错而不是忽略
+    let source = match (&opts.chars, opts.charset) {
+        (Some(_), _) => Some("--chars".to_string()),
+        (None, Some(charset)) => Some(format!("--charset {}", charset)),
+        (None, None) => None,
+    };
+    if let (Some(charset), Some(_)) = (opts.charset, &opts.symbols) {
+        if symbol.is_empty() {
+            bail!(
+                "--charset {} has no symbols for --symbols to replace",
+                charset
+            );
+        }
+    }
+    // hex 和 pin 要用到所有数字, 不排除 0; --chars 明确给出的字符都用
+    let ambiguous = match opts.charset {
+        _ if opts.chars.is_some() => none,
+        Some(Charset::Base58) => AMBIGUOUS,
+        Some(Charset::Hex | Charset::Pin) => none,
+        _ if opts.include_ambiguous => none,
+        _ => AMBIGUOUS,
+    };
+
+    let classes = [
+        ("upper", upper, opts.upper, policy.min_upper),
+        ("lower", lower, opts.lower, policy.min_lower),
+        ("number", number, opts.number, policy.min_number),
+        ("symbol", symbol, opts.symbol, policy.min_symbol),
+    ];
+    let mut required = Vec::new();
     let mut chars = Vec::new();
+    for (name, class, enabled, min) in classes {
+        if !enabled {
+            if min.is_some_and(|n| n > 0) {
+                bail!("--min-{} needs --{} true", name, name);
+            }
+            continue;
+        }
+        if class.is_empty() {
+            match &source {
+                Some(source) if min.is_some_and(|n| n > 0) => {
+                    bail!("{} has no {} characters", source, name)
+                }
+                _ => continue,
+            }
+        }
+        let class: Vec<u8> = class
+            .iter()
+            .copied()
+            .filter(|c| !ambiguous.contains(c) && !policy.exclude_chars.as_bytes().contains(c))
+            .collect();
+        let min = min.unwrap_or(1) as usize;
+        if class.is_empty() {
+            if min > 0 {
+                bail!("--exclude-chars leaves no {} characters", name);
+            }
+            continue;
+        }
+        chars.extend_from_slice(&class);
+        required.push((class, min));
+    }
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
+    if chars.is_empty() {
+        bail!("No characters left to generate a password from");
     }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
+    let min_total: usize = required.iter().map(|(_, min)| min).sum();
+    if min_total > length {
+        bail!(
+            "The policy requires at least {} characters but --length is {}",
+            min_total,
+            length
+        );
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
+    // 每个字符都从 chars 中均匀选取的估算, 每类至少一个的要求只让它略微偏高
+    let entropy = length as f64 * (chars.len() as f64).log2();
+    check_entropy(entropy, policy)?;
+    if chars.len() == 1 && policy.max_repeat.is_some_and(|max| (max as usize) < length) {
+        bail!("--max-repeat cannot be met with a single character to choose from");
     }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
+
+    let mut rng = rand::thread_rng();
+    let password = regenerate(policy, || {
+        let mut password = Vec::with_capacity(length);
+        for (class, min) in &required {
+            for _ in 0..*min {
+                password.push(*class.choose(&mut rng).expect("class won't be empty"));
+            }
+        }
+        while password.len() < length {
+            let c = chars
+                .choose(&mut rng)
+                .expect("chars won't be empty in this context");
+            password.push(*c);
+        }
+
+        // shield password
+        password.shuffle(&mut rng);
+        Ok(String::from_utf8(password)?)
+    })?;
+    Ok((password, entropy))
+}
+
+/// Generate a diceware passphrase of `--words` words, drawn from `wordlist` or the
+/// embedded EFF large wordlist.
+///
+/// Words containing `--separator` are skipped, so the passphrase splits back into
+/// its words.
+///
+/// Returns the passphrase and its entropy in bits, which only depends on how it was
+/// generated, not on the words that came out.
+pub fn process_passphrase(
+    opts: &PassphraseOptions,
+    wordlist: Option<&str>,
+) -> Result<(String, f64)> {
+    let count = opts.words;
+    if count == 0 {
+        bail!("--words must be at least 1");
     }
+    let words = parse_wordlist(wordlist.unwrap_or(EFF_WORDLIST), &opts.separator)?;
 
-    for _ in 0..(length - password.len() as u8) {
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+    let mut entropy = count as f64 * (words.len() as f64).log2();
+    if opts.capitalize == Capitalize::Random {
+        entropy += count as f64;
+    }
+    let symbols = opts.symbols.as_deref().map_or(SYMBOL, str::as_bytes);
+    if opts.append_digit {
+        entropy += (NUMBER.len() as f64).log2();
+    }
+    if opts.append_symbol {
+        entropy += (symbols.len() as f64).log2();
     }
+    check_entropy(entropy, &opts.policy)?;
+
+    let mut rng = rand::thread_rng();
+    let passphrase = regenerate(&opts.policy, || {
+        let mut passphrase = (0..count)
+            .map(|_| {
+                let word = *words.choose(&mut rng).expect("wordlist won't be empty");
+                match opts.capitalize {
+                    Capitalize::None => word.to_string(),
+                    Capitalize::First => capitalize(word),
+                    Capitalize::Upper => word.to_uppercase(),
+                    Capitalize::Random if rng.gen() => capitalize(word),
+                    Capitalize::Random => word.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(&opts.separator);
+        if opts.append_digit {
+            passphrase.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty") as char);
+        }
+        if opts.append_symbol {
+            passphrase.push(*symbols.choose(&mut rng).expect("symbols won't be empty") as char);
+        }
+        Ok(passphrase)
+    })?;
+    Ok((passphrase, entropy))
+}
 
-    // shield password
-    password.shuffle(&mut rng);
+/// Generate `--count` passwords, or passphrases with `--words`, and write each one
+/// with its zxcvbn score, offline crack time and entropy as a record.
+pub fn process_genpass_records(
+    opts: &GenPassOps,
+    wordlist: Option<&str>,
+    writer: &mut dyn FinishWrite,
+    format: OutputFormat,
+) -> Result<()> {
+    let password_opts = GenPassOptions::from(opts);
+    let passphrase_opts = PassphraseOptions::from(opts);
+    let mut output = record_writer(writer, format);
+    for _ in 0..opts.count {
+        let (password, entropy) = match opts.words {
+            Some(_) => process_passphrase(&passphrase_opts, wordlist)?,
+            None => process_genpass(&password_opts)?,
+        };
+        let estimate = zxcvbn(&password, &[])?;
+        // 按离线慢哈希 (每秒 1 万次) 估算, 和 zxcvbn 自己展示的一致
+        let crack_time = estimate.crack_times().offline_slow_hashing_1e4_per_second();
+        output.write_record(&json!({
+            "password": password,
+            "score": estimate.score(),
+            "crack_time": crack_time.to_string(),
+            "entropy": (entropy * 10.0).round() / 10.0,
+        }))?;
+    }
+    output.finish()
+}
 
-    Ok(String::from_utf8(password)?)
+/// Call `generate` until it returns a password within the repeat and score limits
+/// of the policy.
+fn regenerate(
+    policy: &PasswordPolicy,
+    mut generate: impl FnMut() -> Result<String>,
+) -> Result<String> {
+    for _ in 0..MAX_ATTEMPTS {
+        let password = generate()?;
+        if policy
+            .max_repeat
+            .is_some_and(|max| longest_run(&password) > max as usize)
+        {
+            continue;
+        }
+        if let Some(min) = policy.min_score {
+            if zxcvbn(&password, &[])?.score() < min {
+                continue;
+            }
+        }
+        return Ok(password);
+    }
+    bail!(
+        "No password met the policy in {} attempts, try a lower --min-score or a higher --max-repeat",
+        MAX_ATTEMPTS
+    )
+}
 
-    // output password strength in stderr
-    // let estimate = zxcvbn(&password, &[])?;
-    // eprintln!("Password strength: {}", estimate.score());
+fn check_entropy(entropy: f64, policy: &PasswordPolicy) -> Result<()> {
+    match policy.min_entropy {
+        Some(min) if entropy < min => bail!(
+            "{:.1} bits of entropy is below the policy minimum of {}",
+            entropy,
+            min
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Length of the longest run of the same character.
+fn longest_run(password: &str) -> usize {
+    let chars: Vec<char> = password.chars().collect();
+    chars
+        .chunk_by(|a, b| a == b)
+        .map(<[char]>::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The distinct words of a wordlist, one per line. Only the last field of a line counts,
+/// so diceware lists with their dice rolls work as they are, `#` lines are comments.
+fn parse_wordlist<'a>(content: &'a str, separator: &str) -> Result<Vec<&'a str>> {
+    let mut seen = HashSet::new();
+    let words: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().last())
+        // 含分隔符的词(例如 EFF 的 t-shirt)会让口令无法按分隔符拆回单词
+        .filter(|word| separator.is_empty() || !word.contains(separator))
+        .filter(|word| seen.insert(*word))
+        .collect();
+    // 重复的词不增加熵, 去重后至少要有两个词可选
+    if words.len() < 2 {
+        bail!("The wordlist needs at least 2 distinct words");
+    }
+    Ok(words)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn passphrase(args: &[&str], wordlist: Option<&str>) -> Result<(String, f64)> {
+        let opts = GenPassOps::parse_from(["genpass"].iter().chain(args));
+        process_passphrase(&PassphraseOptions::from(&opts), wordlist)
+    }
+
+    fn genpass(args: &[&str]) -> Result<(String, f64)> {
+        let opts = GenPassOps::parse_from(["genpass"].iter().chain(args));
+        process_genpass(&GenPassOptions::from(&opts))
+    }
+
+    #[test]
+    fn test_process_genpass_policy() -> Result<()> {
+        let args = [
+            "-l",
+            "24",
+            "--symbol",
+            "false",
+            "--min-upper",
+            "3",
+            "--min-number",
+            "5",
+            "--exclude-chars",
+            "ABC123",
+            "--max-repeat",
+            "2",
+            "--min-score",
+            "4",
+        ];
+        for _ in 0..20 {
+            let (ret, entropy) = genpass(&args)?;
+            assert_eq!(ret.len(), 24);
+            assert!(ret.bytes().filter(u8::is_ascii_uppercase).count() >= 3);
+            assert!(ret.bytes().filter(u8::is_ascii_digit).count() >= 5);
+            assert!(ret.bytes().any(|c| c.is_ascii_lowercase()));
+            assert!(!ret
+                .bytes()
+                .any(|c| SYMBOL.contains(&c) || b"ABC123".contains(&c)));
+            assert!(longest_run(&ret) <= 2);
+            assert_eq!(zxcvbn(&ret, &[])?.score(), 4);
+            assert!((entropy - 24.0 * 52f64.log2()).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_genpass_unsatisfiable() {
+        let err = |args: &[&str]| genpass(args).unwrap_err().to_string();
+        assert_eq!(
+            err(&["-l", "8", "--min-length", "12"]),
+            "--length 8 is shorter than the policy minimum of 12"
+        );
+        assert_eq!(
+            err(&["-l", "4", "--min-upper", "2", "--min-lower", "2"]),
+            "The policy requires at least 6 characters but --length is 4"
+        );
+        assert_eq!(
+            err(&["--number", "false", "--min-number", "2"]),
+            "--min-number needs --number true"
+        );
+        assert_eq!(
+            err(&["--exclude-chars", "123456789"]),
+            "--exclude-chars leaves no number characters"
+        );
+        assert_eq!(
+            err(&["-l", "8", "--min-entropy", "60"]),
+            "48.5 bits of entropy is below the policy minimum of 60"
+        );
+        let args = [
+            "-l",
+            "6",
+            "--upper",
+            "false",
+            "--lower",
+            "false",
+            "--symbol",
+            "false",
+            "--min-score",
+            "4",
+        ];
+        assert!(err(&args).starts_with("No password met the policy in 1000 attempts"));
+    }
+
+    #[test]
+    fn test_process_genpass_charset() -> Result<()> {
+        let (ret, _) = genpass(&["-l", "64"])?;
+        assert!(!ret.bytes().any(|c| AMBIGUOUS.contains(&c)));
+        let (ret, _) = process_genpass(&GenPassOptions::new(64))?;
+        assert_eq!(ret.len(), 64);
+        assert!(!ret.bytes().any(|c| AMBIGUOUS.contains(&c)));
+
+        let (ret, entropy) = genpass(&["-l", "32", "--charset", "hex"])?;
+        assert!(ret.bytes().all(|c| b"0123456789abcdef".contains(&c)));
+        assert!(ret.bytes().any(|c| c.is_ascii_digit()));
+        assert!((entropy - 128.0).abs() < 1e-9);
+
+        let (ret, entropy) = genpass(&["-l", "6", "--charset", "pin"])?;
+        assert!(ret.bytes().all(|c| c.is_ascii_digit()));
+        assert!((entropy - 6.0 * 10f64.log2()).abs() < 1e-9);
+
+        let (ret, entropy) = genpass(&["-l", "64", "--charset", "base58"])?;
+        assert!(ret
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() && !AMBIGUOUS.contains(&c)));
+        assert!((entropy - 64.0 * 58f64.log2()).abs() < 1e-9);
+
+        // 每个出现的类别至少一个, 容易看错的字符也照用
+        for _ in 0..20 {
+            let (ret, entropy) = genpass(&["-l", "4", "--chars", "aabcO0-"])?;
+            assert!(ret.bytes().all(|c| b"abcO0-".contains(&c)));
+            assert!(ret.contains(['a', 'b', 'c']));
+            assert!(ret.contains(['O']) && ret.contains(['0']) && ret.contains(['-']));
+            assert!((entropy - 4.0 * 6f64.log2()).abs() < 1e-9);
+        }
+
+        let args = [
+            "-l",
+            "12",
+            "--symbols",
+            "-_",
+            "--include-ambiguous",
+            "--exclude-chars",
+            "_",
+        ];
+        let (ret, entropy) = genpass(&args)?;
+        assert!(ret.contains('-'));
+        assert!(!ret.bytes().any(|c| b"!@#$%^&*_".contains(&c)));
+        assert!((entropy - 12.0 * 63f64.log2()).abs() < 1e-9);
+
+        let err = |args: &[&str]| genpass(args).unwrap_err().to_string();
+        assert_eq!(
+            err(&["--charset", "hex", "--min-upper", "1"]),
+            "--charset hex has no upper characters"
+        );
+        assert_eq!(
+            err(&["--charset", "alnum", "--symbols", "-_"]),
+            "--charset alnum has no symbols for --symbols to replace"
+        );
+        assert_eq!(
+            err(&["--charset", "base58", "--include-ambiguous"]),
+            "--charset base58 never includes ambiguous characters, drop --include-ambiguous"
+        );
+        assert_eq!(
+            err(&["--chars", "abc", "--min-number", "2"]),
+            "--chars has no number characters"
+        );
+        assert!(GenPassOps::try_parse_from(["genpass", "--symbols", "ab"]).is_err());
+        assert!(GenPassOps::try_parse_from(["genpass", "--chars", "a b"]).is_err());
+        assert!(
+            GenPassOps::try_parse_from(["genpass", "--chars", "ab", "--charset", "hex"]).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_genpass_records() -> Result<()> {
+        let records = |args: &[&str], format| -> Result<String> {
+            let opts = GenPassOps::parse_from(["genpass"].iter().chain(args));
+            let mut buf = Vec::new();
+            process_genpass_records(&opts, None, &mut buf, format)?;
+            Ok(String::from_utf8(buf)?)
+        };
+
+        let ret = records(&["-n", "3", "--charset", "hex"], OutputFormat::Json)?;
+        let ret: Vec<serde_json::Value> = serde_json::from_str(&ret)?;
+        assert_eq!(ret.len(), 3);
+        for record in &ret {
+            let password = record["password"].as_str().unwrap();
+            assert_eq!(password.len(), 16);
+            let estimate = zxcvbn(password, &[])?;
+            assert_eq!(record["score"], estimate.score());
+            assert!(record["crack_time"].is_string());
+            assert_eq!(record["entropy"], 64.0);
+        }
+
+        let ret = records(&["--count", "2", "--words", "4"], OutputFormat::Csv)?;
+        let lines: Vec<&str> = ret.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "password,score,crack_time,entropy");
+        assert!(lines[1].ends_with(",51.7"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_passphrase_eff() -> Result<()> {
+        assert_eq!(parse_wordlist(EFF_WORDLIST, " ")?.len(), 7776);
+        // drop-down, felt-tip, t-shirt and yo-yo contain the default separator
+        assert_eq!(parse_wordlist(EFF_WORDLIST, "-")?.len(), 7772);
+
+        for _ in 0..20 {
+            let (ret, entropy) = passphrase(&["--words", "5"], None)?;
+            let words: Vec<&str> = ret.split('-').collect();
+            assert_eq!(words.len(), 5);
+            assert!(words
+                .iter()
+                .all(|w| EFF_WORDLIST.contains(&format!("\t{}\n", w))));
+            assert!((entropy - 5.0 * 7772f64.log2()).abs() < 1e-9);
+        }
+
+        let (ret, _) = process_passphrase(&PassphraseOptions::new(4), None)?;
+        assert_eq!(ret.split('-').count(), 4);
+        assert_eq!(ret, ret.to_lowercase());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_passphrase_options() -> Result<()> {
+        let wordlist = "# dice\n1 alpha\n2 beta\n\n3 alpha\n4 gamma\n5 delta\n";
+        let args = [
+            "--words",
+            "3",
+            "--separator",
+            " ",
+            "--capitalize",
+            "first",
+            "--append-digit",
+            "--append-symbol",
+        ];
+        let (ret, entropy) = passphrase(&args, Some(wordlist))?;
+        let (words, suffix) = ret.split_at(ret.len() - 2);
+        for word in words.split(' ') {
+            assert!(["Alpha", "Beta", "Gamma", "Delta"].contains(&word));
+        }
+        assert!(NUMBER.contains(&suffix.as_bytes()[0]));
+        assert!(SYMBOL.contains(&suffix.as_bytes()[1]));
+        let expected = 3.0 * 2.0 + 10f64.log2() + 9f64.log2();
+        assert!((entropy - expected).abs() < 1e-9);
+
+        let (ret, entropy) =
+            passphrase(&["--words", "4", "--capitalize", "random"], Some(wordlist))?;
+        assert_eq!(ret.split('-').count(), 4);
+        assert!((entropy - 12.0).abs() < 1e-9);
+
+        assert!(passphrase(&["--words", "3"], Some("one\none\n")).is_err());
+        assert!(passphrase(&["--words", "0"], None).is_err());
+        // 只对字符密码有意义的策略不能和 --words 一起用
+        for flag in [
+            "--min-length",
+            "--min-upper",
+            "--min-number",
+            "--min-symbol",
+        ] {
+            let args = ["genpass", "--words", "4", flag, "1"];
+            assert!(GenPassOps::try_parse_from(args).is_err());
+        }
+        let args = ["genpass", "--words", "4", "--exclude-chars", "a"];
+        assert!(GenPassOps::try_parse_from(args).is_err());
+        Ok(())
+    }
 }
@@ -1,11 +1,47 @@
 mod b64;
+mod csv_agg;
+mod csv_columnar;
 mod csv_convert;
+mod csv_diff;
+mod csv_encoding;
+mod csv_excel;
+mod csv_filter;
+mod csv_from;
+mod csv_infer;
+mod csv_join;
+mod csv_mask;
+mod csv_output;
+mod csv_parallel;
+mod csv_query;
+mod csv_show;
+mod csv_sort;
+mod csv_validate;
 mod gen_pass;
 mod http_serve;
+mod password_audit;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_agg::process_csv_agg;
+pub use csv_convert::{
+    csv_headers, csv_reader, csv_writer, process_csv, process_csv_split, RecordConverter,
+};
+pub use csv_diff::process_csv_diff;
+pub use csv_encoding::{decode_reader, encode_writer};
+pub use csv_excel::csv_input_reader;
+pub use csv_filter::Filter;
+pub use csv_from::process_csv_from;
+pub use csv_infer::{infer_value, parse_value};
+pub use csv_join::process_csv_join;
+pub use csv_mask::process_csv_mask;
+pub use csv_output::{cell, delimited_writer, record_writer, RecordWriter};
+pub use csv_query::process_csv_query;
+pub use csv_show::{process_csv_show, render_table};
+pub use csv_sort::process_csv_sort;
+pub use csv_validate::process_csv_validate;
+pub use gen_pass::{
+    process_genpass, process_genpass_records, process_passphrase, GenPassOptions, PassphraseOptions,
+};
 pub use http_serve::process_http_serve;
+pub use password_audit::process_password_audit;
 pub use text::{process_text_key_generate, process_text_sign, process_text_verify};
@@ -0,0 +1,280 @@
+use std::io::{BufRead, BufReader, Read};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use zxcvbn::{matching::patterns::MatchPattern, zxcvbn, Match};
+
+use super::csv_output::{cell, record_writer};
+use crate::cli::{OutputFormat, PasswordAuditOps};
+use crate::FinishWrite;
+
+/// Estimate the strength of every password read from `reader`, one per line, and write
+/// a report with the score, crack times, feedback and matched patterns of each.
+///
+/// The `--user-input` words count as a dictionary, so passwords built from them score
+/// lower. Returns how many passwords score below `--min-score`.
+pub fn process_password_audit(
+    reader: &mut dyn Read,
+    writer: &mut dyn FinishWrite,
+    opts: &PasswordAuditOps,
+) -> Result<usize> {
+    let user_inputs: Vec<&str> = opts.user_inputs.iter().map(String::as_str).collect();
+    let (mut total, mut weak) = (0, 0);
+    // 逐行审计并立即写出, 不在内存中保留全部报告
+    let reports = BufReader::new(reader)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match line {
+            Ok(line) => {
+                let password = line.trim_end_matches('\r');
+                (!password.is_empty()).then(|| audit(password, i + 1, &user_inputs, opts.reveal))
+            }
+            Err(e) => Some(Err(e.into())),
+        })
+        .inspect(|report| {
+            if let Ok(report) = report {
+                total += 1;
+                if report["score"].as_u64() < Some(opts.min_score.into()) {
+                    weak += 1;
+                }
+            }
+        });
+
+    match opts.format {
+        Some(format) => {
+            let delimited = matches!(format, OutputFormat::Csv | OutputFormat::Tsv);
+            let mut output = record_writer(writer, format);
+            for report in reports {
+                let report = report?;
+                if delimited {
+                    output.write_record(&flatten(&report))?;
+                } else {
+                    output.write_record(&report)?;
+                }
+            }
+            output.finish()?;
+        }
+        None => {
+            for report in reports {
+                writer.write_all(render(&report?, opts.min_score).as_bytes())?;
+            }
+            writeln!(
+                writer,
+                "{} passwords, {} below score {}",
+                total, weak, opts.min_score
+            )?;
+            writer.finish()?;
+        }
+    }
+    Ok(weak)
+}
+
+fn audit(password: &str, line: usize, user_inputs: &[&str], reveal: bool) -> Result<Value> {
+    let estimate = zxcvbn(password, user_inputs)?;
+    let times = estimate.crack_times();
+    let (warning, suggestions) = match estimate.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|w| w.to_string()),
+            feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+    // 不显示密码时 pattern 里也不带匹配到的片段
+    let patterns: Vec<String> = estimate
+        .sequence()
+        .iter()
+        .map(|m| describe(m, reveal))
+        .collect();
+
+    let mut report = json!({ "line": line });
+    if reveal {
+        report["password"] = json!(password);
+    }
+    report["score"] = json!(estimate.score());
+    report["crack_time"] = json!({
+        "online_throttled": times.online_throttling_100_per_hour().to_string(),
+        "online": times.online_no_throttling_10_per_second().to_string(),
+        "offline_slow_hash": times.offline_slow_hashing_1e4_per_second().to_string(),
+        "offline_fast_hash": times.offline_fast_hashing_1e10_per_second().to_string(),
+    });
+    report["warning"] = json!(warning);
+    report["suggestions"] = json!(suggestions);
+    report["patterns"] = json!(patterns);
+    Ok(report)
+}
+
+/// e.g. `dictionary (user inputs, l33t) "p4ssw0rd"`
+fn describe(m: &Match, reveal: bool) -> String {
+    let kind = match &m.pattern {
+        MatchPattern::Dictionary(d) => {
+            let mut details = vec![dictionary_name(d.dictionary_name).to_string()];
+            if d.reversed {
+                details.push("reversed".to_string());
+            }
+            if d.l33t {
+                details.push("l33t".to_string());
+            }
+            format!("dictionary ({})", details.join(", "))
+        }
+        MatchPattern::Spatial(_) => "spatial".to_string(),
+        MatchPattern::Repeat(_) => "repeat".to_string(),
+        MatchPattern::Sequence(_) => "sequence".to_string(),
+        MatchPattern::Regex(r) => format!("regex ({})", r.regex_name),
+        MatchPattern::Date(_) => "date".to_string(),
+        MatchPattern::BruteForce => "bruteforce".to_string(),
+    };
+    if reveal {
+        format!("{} \"{}\"", kind, m.token)
+    } else {
+        kind
+    }
+}
+
+// zxcvbn 2 没有导出 DictionaryType, 无法按变体匹配, 只能按变体名查固定的表;
+// 以后新增的变体显示为 other
+const DICTIONARY_NAMES: &[(&str, &str)] = &[
+    ("Passwords", "passwords"),
+    ("English", "english"),
+    ("FemaleNames", "female names"),
+    ("MaleNames", "male names"),
+    ("Surnames", "surnames"),
+    ("UsTvAndFilm", "us tv and film"),
+    ("UserInputs", "user inputs"),
+];
+
+fn dictionary_name(dictionary: impl std::fmt::Debug) -> &'static str {
+    let variant = format!("{:?}", dictionary);
+    DICTIONARY_NAMES
+        .iter()
+        .find(|(name, _)| *name == variant)
+        .map_or("other", |(_, text)| text)
+}
+
+/// One level of columns for csv / tsv: `crack_time.online` and lists joined by `; `.
+fn flatten(report: &Value) -> Value {
+    let mut row = Map::new();
+    for (key, value) in report.as_object().into_iter().flatten() {
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map {
+                    row.insert(format!("{}.{}", key, k), v.clone());
+                }
+            }
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(cell).collect();
+                row.insert(key.clone(), Value::String(items.join("; ")));
+            }
+            v => {
+                row.insert(key.clone(), v.clone());
+            }
+        }
+    }
+    Value::Object(row)
+}
+
+fn render(report: &Value, min_score: u8) -> String {
+    let mut ret = format!("line {}", report["line"]);
+    if let Some(password) = report["password"].as_str() {
+        ret.push_str(&format!(" \"{}\"", password));
+    }
+    ret.push_str(&format!(": score {}", report["score"]));
+    if report["score"].as_u64() < Some(min_score.into()) {
+        ret.push_str(&format!(" (below {})", min_score));
+    }
+    let times = &report["crack_time"];
+    ret.push_str(&format!(
+        "\n  crack time: {} online throttled, {} online, {} offline slow hash, {} offline fast hash\n",
+        cell(&times["online_throttled"]),
+        cell(&times["online"]),
+        cell(&times["offline_slow_hash"]),
+        cell(&times["offline_fast_hash"]),
+    ));
+    if let Some(warning) = report["warning"].as_str() {
+        ret.push_str(&format!("  warning: {}\n", warning));
+    }
+    for suggestion in report["suggestions"].as_array().into_iter().flatten() {
+        ret.push_str(&format!("  suggestion: {}\n", cell(suggestion)));
+    }
+    let patterns: Vec<String> = report["patterns"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(cell)
+        .collect();
+    ret.push_str(&format!("  patterns: {}\n", patterns.join(", ")));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn audit_all(input: &str, args: &[&str]) -> Result<(usize, String)> {
+        let opts = PasswordAuditOps::parse_from(["audit"].iter().chain(args));
+        let mut buf = Vec::new();
+        let weak = process_password_audit(&mut input.as_bytes(), &mut buf, &opts)?;
+        Ok((weak, String::from_utf8(buf)?))
+    }
+
+    #[test]
+    fn test_process_password_audit_text() -> Result<()> {
+        let (weak, ret) = audit_all("password\n\ncorrect horse battery staple\r\n", &[])?;
+        assert_eq!(weak, 1);
+        let lines: Vec<&str> = ret.lines().collect();
+        assert_eq!(lines[0], "line 1: score 0 (below 3)");
+        assert!(lines[1].starts_with("  crack time: "));
+        assert_eq!(lines[2], "  warning: This is a top-10 common password.");
+        assert!(lines.contains(&"line 3: score 4"));
+        assert!(!ret.contains("password\""));
+        assert_eq!(lines.last(), Some(&"2 passwords, 1 below score 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_password_audit_user_inputs() -> Result<()> {
+        let input = "Acmecorp2024!\n";
+        let (weak, _) = audit_all(input, &["--min-score", "4"])?;
+        assert_eq!(weak, 0);
+
+        let args = [
+            "-u",
+            "jdoe,acmecorp",
+            "--min-score",
+            "4",
+            "--format",
+            "json",
+            "--reveal",
+        ];
+        let (weak, ret) = audit_all(input, &args)?;
+        assert_eq!(weak, 1);
+        let ret: Vec<Value> = serde_json::from_str(&ret)?;
+        assert_eq!(ret[0]["line"], 1);
+        assert_eq!(ret[0]["password"], "Acmecorp2024!");
+        assert!(ret[0]["score"].as_u64() < Some(4));
+        let patterns = ret[0]["patterns"].as_array().unwrap();
+        assert_eq!(patterns[0], "dictionary (user inputs) \"Acmecorp\"");
+        assert!(ret[0]["crack_time"]["offline_fast_hash"].is_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_password_audit_csv() -> Result<()> {
+        let args = ["-u", "acmecorp", "--format", "csv", "--reveal"];
+        let (_, ret) = audit_all("Acmecorp2024!\n", &args)?;
+        let mut reader = csv::Reader::from_reader(ret.as_bytes());
+        let headers = reader.headers()?.clone();
+        assert!(headers.iter().any(|h| h == "crack_time.offline_fast_hash"));
+        assert!(!headers.iter().any(|h| h == "crack_time"));
+        let record = reader.records().next().unwrap()?;
+        let column = |name: &str| &record[headers.iter().position(|h| h == name).unwrap()];
+        assert!(column("patterns").starts_with("dictionary (user inputs) \"Acmecorp\"; "));
+        assert!(!column("crack_time.online").is_empty());
+        Ok(())
+    }
+}
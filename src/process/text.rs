@@ -8,7 +8,7 @@ use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
 use rand::rngs::OsRng;
 
 use crate::cli::TextSignFormat;
-use crate::process_genpass;
+use crate::{process_genpass, GenPassOptions};
 
 pub trait TextSigner {
     /// Sign the data from the reader and return the signature
@@ -31,14 +31,17 @@ impl Blake3 {
     }
 
     pub fn try_new(key: &[u8]) -> Result<Self> {
-        let key = &key[..32];
+        let key = key
+            .get(..32)
+            .ok_or_else(|| anyhow::anyhow!("Blake3 key must be at least 32 bytes"))?;
         let key = key.try_into()?;
         let signer = Blake3::new(key);
         Ok(signer)
     }
 
     fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        // 32 位, 默认四类字符都有
+        let (key, _) = process_genpass(&GenPassOptions::new(32))?;
 
         let mut map = HashMap::new();
         map.insert("blake3.txt", key.as_bytes().to_vec());
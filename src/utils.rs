@@ -1,19 +1,209 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Chain, Cursor, Read, Write},
+    path::Path,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
         Box::new(std::io::stdin())
     } else {
-        Box::new(File::open(input)?)
+        Box::new(File::open(input).with_context(|| format!("cannot open {}", input))?)
     };
     Ok(reader)
 }
 
+/// Like `get_reader`, but gzip, zstd, bzip2 and xz input is decompressed on the fly.
+///
+/// The compression is recognized by its magic bytes, so it also works on stdin.
+pub fn get_uncompressed_reader(input: &str) -> Result<Box<dyn Read>> {
+    let (magic, reader) = sniff(get_reader(input)?, 6)?;
+    let reader = BufReader::new(reader);
+    let reader: Box<dyn Read> = if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else if magic.starts_with(b"BZh") {
+        Box::new(bzip2::bufread::MultiBzDecoder::new(reader))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(reader)
+}
+
+/// A reader that replays the bytes already sniffed before the rest of the input.
+pub type Sniffed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Read the first `len` bytes, fewer only at the end of the input, and a reader that
+/// still starts with them.
+///
+/// A single read of a pipe may return fewer bytes than are on the way, so they are read
+/// until there are enough.
+pub fn sniff<R: Read>(mut reader: R, len: usize) -> io::Result<(Vec<u8>, Sniffed<R>)> {
+    let mut head = Vec::with_capacity(len);
+    (&mut reader).take(len as u64).read_to_end(&mut head)?;
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+/// A writer whose end has to be written explicitly, like the trailer of a compressed
+/// stream. Dropping it would finish it too, but lose the errors.
+pub trait FinishWrite: Write {
+    /// Flush everything and end the stream.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl FinishWrite for io::Sink {
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FinishWrite for Vec<u8> {
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> FinishWrite for BufWriter<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: FinishWrite + ?Sized> FinishWrite for &mut W {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<W: FinishWrite + ?Sized> FinishWrite for Box<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<W: Write> FinishWrite for flate2::write::GzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> FinishWrite for zstd::Encoder<'static, W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.do_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> FinishWrite for bzip2::write::BzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> FinishWrite for xz2::write::XzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+/// Create the output file, compressed when it ends with `.gz`, `.zst`, `.bz2` or `.xz`.
+/// `-` is stdout.
+///
+/// Call `finish` when done, otherwise a failure to write the end of a compressed stream
+/// goes unnoticed.
+pub fn get_writer(output: &str) -> Result<Box<dyn FinishWrite>> {
+    if output == "-" {
+        return Ok(Box::new(BufWriter::new(std::io::stdout().lock())));
+    }
+    let file = BufWriter::new(File::create(output)?);
+    let ext = Path::new(output)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let writer: Box<dyn FinishWrite> = match ext {
+        "gz" => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )),
+        "zst" => Box::new(zstd::Encoder::new(file, 0)?),
+        "bz2" => Box::new(bzip2::write::BzEncoder::new(
+            file,
+            bzip2::Compression::default(),
+        )),
+        "xz" => Box::new(xz2::write::XzEncoder::new(file, 6)),
+        _ => Box::new(file),
+    };
+    Ok(writer)
+}
+
 pub fn get_content(input: &str) -> Result<Vec<u8>> {
     let mut reader = get_reader(input)?;
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content = std::fs::read("assets/juventus.csv")?;
+        for ext in ["csv", "csv.gz", "csv.zst", "csv.bz2", "csv.xz"] {
+            let path = dir.path().join(format!("juventus.{}", ext));
+            let path = path.to_str().unwrap();
+            let mut writer = get_writer(path)?;
+            writer.write_all(&content)?;
+            writer.finish()?;
+            drop(writer);
+
+            let mut buf = Vec::new();
+            get_uncompressed_reader(path)?.read_to_end(&mut buf)?;
+            assert_eq!(buf, content, "{}", ext);
+            if ext != "csv" {
+                assert_ne!(std::fs::read(path)?, content, "{}", ext);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands out one byte per read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_sniff_slow_reader() -> Result<()> {
+        let mut compressed = xz2::write::XzEncoder::new(Vec::new(), 6);
+        compressed.write_all(b"Name\nBuffon\n")?;
+        let compressed = compressed.finish()?;
+        let (magic, _) = sniff(Trickle(&compressed), 6)?;
+        assert_eq!(magic, [0xfd, b'7', b'z', b'X', b'Z', 0x00]);
+
+        let (magic, mut reader) = sniff(Trickle(b"ab"), 6)?;
+        assert_eq!(magic, b"ab");
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf)?;
+        assert_eq!(buf, "ab");
+        Ok(())
+    }
+}
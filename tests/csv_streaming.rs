@@ -0,0 +1,99 @@
+// Runs in its own test binary so the counting allocator only sees this conversion.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{self, Read},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Result;
+use clap::Parser;
+use rcli::{process_csv, CsvOps};
+
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(now, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Produces a CSV file of `rows` rows on the fly, without holding it in memory.
+struct GeneratedCsv {
+    rows: usize,
+    next: usize,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl GeneratedCsv {
+    fn new(rows: usize) -> Self {
+        Self {
+            rows,
+            next: 0,
+            line: b"Name,Position,DOB,Nationality,Kit Number\n".to_vec(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for GeneratedCsv {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            if self.next == self.rows {
+                return Ok(0);
+            }
+            self.line.clear();
+            let i = self.next;
+            self.line.extend_from_slice(
+                format!(
+                    "Player {i},Goalkeeper,\"Apr 18, 1990 (29)\",Italy,{}\n",
+                    i % 99
+                )
+                .as_bytes(),
+            );
+            self.next += 1;
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_process_csv_memory_is_bounded() -> Result<()> {
+    // ~3 MB of CSV; buffering every record as a `Value` needs several times the budget
+    const ROWS: usize = 50_000;
+    const BUDGET: usize = 4 * 1024 * 1024;
+
+    for format in ["json", "yaml"] {
+        let opts = CsvOps::parse_from(["csv", "-i", "-", "--format", format]);
+        let mut reader = GeneratedCsv::new(ROWS);
+        let mut writer = io::sink();
+        let base = CURRENT.load(Ordering::SeqCst);
+        PEAK.store(base, Ordering::SeqCst);
+
+        process_csv(&mut reader, &mut writer, &opts)?;
+
+        let used = PEAK.load(Ordering::SeqCst) - base;
+        assert!(used < BUDGET, "{format} conversion used {used} bytes");
+    }
+    Ok(())
+}